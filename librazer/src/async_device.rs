@@ -0,0 +1,239 @@
+use crate::commands::{self, RazerCommand};
+use crate::descriptor::Descriptor;
+use crate::device::Device;
+use crate::packet::Packet;
+use crate::shared::{Priority, SharedDevice};
+use crate::types::{
+    BatteryCare, Cluster, CpuBoost, FanMode, FanZone, GpuBoost, LightsAlwaysOn, LogoMode,
+    MaxFanSpeedMode, PerfMode,
+};
+use crate::{command, Result};
+
+use std::panic;
+use std::time;
+use tokio::sync::oneshot;
+
+/// Async front of a `SharedDevice`, for use from a tokio runtime.
+///
+/// HID I/O runs on the device worker thread, the runtime is never blocked. `send` sleeps
+/// between retries asynchronously, and the command wrappers, the async counterparts of the
+/// `command` functions, are built on it: the worker is free for other jobs between attempts.
+#[derive(Clone)]
+pub struct AsyncDevice {
+    shared: SharedDevice,
+    priority: Priority,
+}
+
+impl AsyncDevice {
+    pub fn new(device: Device) -> AsyncDevice {
+        AsyncDevice::from(SharedDevice::new(device))
+    }
+
+    /// Handle to the same device whose transactions are queued with the given priority, e.g.
+    /// `Priority::Background` for polling.
+    pub fn with_priority(&self, priority: Priority) -> AsyncDevice {
+        AsyncDevice {
+            shared: self.shared.clone(),
+            priority,
+        }
+    }
+
+    pub fn info(&self) -> &Descriptor {
+        self.shared.info()
+    }
+
+    /// Run `transaction` on the device worker.
+    pub async fn run<R: Send + 'static>(
+        &self,
+        transaction: impl FnOnce(&Device) -> R + Send + 'static,
+    ) -> R {
+        let (sender, receiver) = oneshot::channel();
+        self.shared
+            .submit(self.priority, transaction, move |result| {
+                let _ = sender.send(result);
+            });
+        match receiver.await.expect("Device worker stopped") {
+            Ok(result) => result,
+            Err(payload) => panic::resume_unwind(payload),
+        }
+    }
+
+    /// Same as `Device::send`, the delays between retries don't hold the worker.
+    pub async fn send(&self, report: Packet) -> Result<Packet> {
+        let start = time::Instant::now();
+        let (report, policy) = self
+            .run(move |device| (device.prepare(report), *device.policy()))
+            .await;
+        log::debug!("report {}", report);
+        let command = report.command();
+        let retry = policy.retry;
+
+        let mut retries = 0;
+        let result = loop {
+            let report = report.clone();
+            match self.run(move |device| device.attempt(&report, false)).await {
+                Err(e) if e.is_transient() && retries + 1 < retry.max_attempts => {
+                    log::debug!("retrying after {}", e);
+                    tokio::time::sleep(retry.delay(retries)).await;
+                    retries += 1;
+                }
+                result => break result,
+            }
+        };
+
+        let ok = result.is_ok();
+        self.run(move |device| device.record(command, start.elapsed(), retries as u32, ok))
+            .await;
+        result
+    }
+
+    pub async fn send_command(&self, command: u16, args: Vec<u8>) -> Result<Packet> {
+        command::ensure_fits(&args)?;
+        self.send(Packet::new(command, &args)).await
+    }
+
+    /// Same as `Device::send_payload`, the transfer runs on the worker as a whole.
+    pub async fn send_payload(&self, command: u16, payload: Vec<u8>) -> Result<Vec<u8>> {
+        self.run(move |device| device.send_payload(command, &payload))
+            .await
+    }
+
+    /// Same as `commands::execute`, built on `send`.
+    pub async fn execute<C: RazerCommand>(&self, request: C::Request) -> Result<C::Response> {
+        let report = Packet::new(C::COMMAND, &C::encode(&request)?);
+        let response = self.send(report.clone()).await?;
+        C::VALIDATION.check(&report, &response)?;
+        C::decode(response.get_args())
+    }
+
+    async fn ensure_custom_perf_mode(&self) -> Result<()> {
+        command::ensure_custom(self.get_perf_mode().await?.0)
+    }
+
+    async fn _set_perf_mode(&self, perf_mode: PerfMode, fan_mode: FanMode) -> Result<()> {
+        for zone in [FanZone::Zone1, FanZone::Zone2] {
+            self.execute::<commands::SetPerfMode>((zone, perf_mode, fan_mode))
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn _set_boost(&self, cluster: Cluster, boost: u8) -> Result<()> {
+        self.ensure_custom_perf_mode().await?;
+        self.execute::<commands::SetBoost>((cluster, boost)).await
+    }
+
+    pub async fn set_perf_mode(&self, perf_mode: PerfMode) -> Result<()> {
+        self._set_perf_mode(perf_mode, FanMode::Auto).await
+    }
+
+    pub async fn get_perf_mode(&self) -> Result<(PerfMode, FanMode)> {
+        let r1 = self
+            .execute::<commands::GetPerfMode>(FanZone::Zone1)
+            .await?;
+        let r2 = self
+            .execute::<commands::GetPerfMode>(FanZone::Zone2)
+            .await?;
+        command::ensure_same_modes(r1, r2)
+    }
+
+    pub async fn set_cpu_boost(&self, boost: CpuBoost) -> Result<()> {
+        self._set_boost(Cluster::Cpu, boost as u8).await
+    }
+
+    pub async fn set_gpu_boost(&self, boost: GpuBoost) -> Result<()> {
+        self._set_boost(Cluster::Gpu, boost as u8).await
+    }
+
+    pub async fn get_cpu_boost(&self) -> Result<CpuBoost> {
+        CpuBoost::try_from(self.execute::<commands::GetBoost>(Cluster::Cpu).await?)
+    }
+
+    pub async fn get_gpu_boost(&self) -> Result<GpuBoost> {
+        GpuBoost::try_from(self.execute::<commands::GetBoost>(Cluster::Gpu).await?)
+    }
+
+    pub async fn set_fan_rpm(&self, rpm: u16, check_mode: bool) -> Result<()> {
+        // fail on the rpm before touching the device
+        commands::SetFanRpm::encode(&(FanZone::Zone1, rpm))?;
+        if check_mode {
+            command::ensure_manual(self.get_perf_mode().await?.1)?;
+        }
+        for zone in [FanZone::Zone1, FanZone::Zone2] {
+            self.execute::<commands::SetFanRpm>((zone, rpm)).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn get_fan_rpm(&self, fan_zone: FanZone) -> Result<u16> {
+        self.execute::<commands::GetFanRpm>(fan_zone).await
+    }
+
+    pub async fn get_fan_actual_rpm(&self, fan_zone: FanZone) -> Result<u16> {
+        self.execute::<commands::GetFanActualRpm>(fan_zone).await
+    }
+
+    pub async fn set_fan_mode(&self, mode: FanMode) -> Result<()> {
+        self._set_perf_mode(self.get_perf_mode().await?.0, mode)
+            .await
+    }
+
+    pub async fn set_max_fan_speed_mode(&self, mode: MaxFanSpeedMode) -> Result<()> {
+        self.ensure_custom_perf_mode().await?;
+        self.execute::<commands::SetMaxFanSpeedMode>(mode).await
+    }
+
+    pub async fn get_max_fan_speed_mode(&self) -> Result<MaxFanSpeedMode> {
+        self.execute::<commands::GetMaxFanSpeedMode>(()).await
+    }
+
+    pub async fn set_logo_mode(&self, mode: LogoMode) -> Result<()> {
+        if mode != LogoMode::Off {
+            self.execute::<commands::SetLogoMode>(mode).await?;
+        }
+        self.execute::<commands::SetLogoPower>(mode != LogoMode::Off)
+            .await
+    }
+
+    pub async fn get_logo_mode(&self) -> Result<LogoMode> {
+        match self.execute::<commands::GetLogoPower>(()).await? {
+            true => self.execute::<commands::GetLogoMode>(()).await,
+            false => Ok(LogoMode::Off),
+        }
+    }
+
+    pub async fn set_keyboard_brightness(&self, brightness: u8) -> Result<()> {
+        self.execute::<commands::SetKeyboardBrightness>(brightness)
+            .await
+    }
+
+    pub async fn get_keyboard_brightness(&self) -> Result<u8> {
+        self.execute::<commands::GetKeyboardBrightness>(()).await
+    }
+
+    pub async fn set_lights_always_on(&self, lights_always_on: LightsAlwaysOn) -> Result<()> {
+        self.execute::<commands::SetLightsAlwaysOn>(lights_always_on)
+            .await
+    }
+
+    pub async fn get_lights_always_on(&self) -> Result<LightsAlwaysOn> {
+        self.execute::<commands::GetLightsAlwaysOn>(()).await
+    }
+
+    pub async fn set_battery_care(&self, mode: BatteryCare) -> Result<()> {
+        self.execute::<commands::SetBatteryCare>(mode).await
+    }
+
+    pub async fn get_battery_care(&self) -> Result<BatteryCare> {
+        self.execute::<commands::GetBatteryCare>(()).await
+    }
+}
+
+impl From<SharedDevice> for AsyncDevice {
+    fn from(shared: SharedDevice) -> Self {
+        AsyncDevice {
+            shared,
+            priority: Priority::Interactive,
+        }
+    }
+}
@@ -0,0 +1,435 @@
+use crate::packet::{CommandStatus, Packet};
+
+use crate::{Error, Result};
+use std::path::Path;
+
+fn invalid(message: impl Into<String>) -> Error {
+    Error::InvalidData(message.into())
+}
+
+/// Razer report found in a USB capture.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CapturedReport {
+    /// Seconds since the start of the capture.
+    pub time: f64,
+    pub command: u16,
+    pub args: Vec<u8>,
+    /// The crc of the report is wrong, e.g. a corrupted or misunderstood transfer.
+    pub crc_mismatch: bool,
+}
+
+/// Action annotated by hand while capturing, e.g. `52 "set custom mode, cpu boost, gpu high"`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Annotation {
+    /// Whole second of the capture the action was performed in.
+    pub time: u64,
+    pub action: String,
+}
+
+/// One row of the action→command table, the action is only set on the first command of a group.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActionRow {
+    pub action: String,
+    pub command: u16,
+    pub args: Vec<u8>,
+    pub crc_mismatch: bool,
+}
+
+const REPORT_SIZE: usize = std::mem::size_of::<Packet>();
+
+// link types, see https://www.tcpdump.org/linktypes.html
+const LINKTYPE_USB_LINUX: u32 = 189;
+const LINKTYPE_USB_LINUX_MMAPPED: u32 = 220;
+const LINKTYPE_USBPCAP: u32 = 249;
+
+/// Parse a 90 byte feature report, keeping only requests sent by the host. Reports with a wrong
+/// crc are kept and marked, they are what reverse engineering has to look at.
+fn parse_report(time: f64, data: &[u8]) -> Option<CapturedReport> {
+    if data.len() != REPORT_SIZE {
+        return None;
+    }
+    let packet = Packet::decode_unchecked(data).ok()?;
+    if packet.status() != CommandStatus::New as u8 {
+        return None;
+    }
+    let crc_mismatch = !packet.crc_matches();
+    if crc_mismatch {
+        log::warn!("CRC mismatch of the report at {:.6}s: {}", time, packet);
+    }
+    Some(CapturedReport {
+        time,
+        command: packet.command(),
+        args: packet.data().to_vec(),
+        crc_mismatch,
+    })
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    let hex = hex.trim();
+    if !hex.is_ascii() || !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+/// Split a line of a Wireshark CSV export, fields are double quoted and `""` escapes a quote.
+fn split_csv_line(line: &str) -> Vec<String> {
+    let mut fields = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, quoted) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            ('"', _) => quoted = !quoted,
+            (',', false) => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+/// Read a Wireshark "Export Packet Dissections as CSV" file with the Time and Data columns.
+pub fn read_csv(content: &str) -> Result<Vec<CapturedReport>> {
+    let mut lines = content.lines();
+    let header = split_csv_line(lines.next().ok_or_else(|| invalid("Empty CSV file"))?);
+    let column = |names: &[&str]| {
+        header
+            .iter()
+            .position(|column| names.contains(&column.as_str()))
+            .ok_or_else(|| invalid(format!("CSV file has no {:?} column", names[0])))
+    };
+    let time_column = column(&["Time"])?;
+    let data_column = column(&["Data", "Leftover Capture Data"])?;
+
+    lines
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .try_fold(vec![], |mut reports, (n, line)| {
+            let fields = split_csv_line(line);
+            let time = fields
+                .get(time_column)
+                .and_then(|time| time.parse::<f64>().ok())
+                .ok_or_else(|| invalid(format!("Invalid time on line {}", n + 2)))?;
+            if let Some(report) = fields
+                .get(data_column)
+                .and_then(|data| from_hex(data))
+                .and_then(|data| parse_report(time, &data))
+            {
+                reports.push(report);
+            }
+            Ok(reports)
+        })
+}
+
+/// Strip the USB pseudo header of the given link type and return the transferred data.
+fn usb_payload(linktype: u32, frame: &[u8], little_endian: bool) -> Option<&[u8]> {
+    match linktype {
+        LINKTYPE_USB_LINUX | LINKTYPE_USB_LINUX_MMAPPED => {
+            let header_len = if linktype == LINKTYPE_USB_LINUX {
+                48
+            } else {
+                64
+            };
+            let len_cap = read_u32(frame.get(36..40)?, little_endian) as usize;
+            frame.get(header_len..header_len + len_cap)
+        }
+        LINKTYPE_USBPCAP => {
+            let header_len = u16::from_le_bytes(frame.get(0..2)?.try_into().ok()?) as usize;
+            let payload = frame.get(header_len..)?;
+            const TRANSFER_CONTROL: u8 = 2;
+            const STAGE_SETUP: u8 = 0;
+            match (frame.get(22), frame.get(27)) {
+                // OUT control transfers carry the setup packet in front of the data
+                (Some(&TRANSFER_CONTROL), Some(&STAGE_SETUP)) => payload.get(8..),
+                _ => Some(payload),
+            }
+        }
+        _ => None,
+    }
+}
+
+fn read_u32(bytes: &[u8], little_endian: bool) -> u32 {
+    let bytes: [u8; 4] = bytes[..4].try_into().unwrap();
+    match little_endian {
+        true => u32::from_le_bytes(bytes),
+        false => u32::from_be_bytes(bytes),
+    }
+}
+
+fn read_u16(bytes: &[u8], little_endian: bool) -> u16 {
+    let bytes: [u8; 2] = bytes[..2].try_into().unwrap();
+    match little_endian {
+        true => u16::from_le_bytes(bytes),
+        false => u16::from_be_bytes(bytes),
+    }
+}
+
+/// Read a classic libpcap file, e.g. produced by `tcpdump -i usbmon1`.
+pub fn read_pcap(data: &[u8]) -> Result<Vec<CapturedReport>> {
+    if data.len() < 24 {
+        return Err(invalid("Truncated pcap header"));
+    }
+    let (little_endian, nanos) = match data[..4] {
+        [0xd4, 0xc3, 0xb2, 0xa1] => (true, false),
+        [0x4d, 0x3c, 0xb2, 0xa1] => (true, true),
+        [0xa1, 0xb2, 0xc3, 0xd4] => (false, false),
+        [0xa1, 0xb2, 0x3c, 0x4d] => (false, true),
+        _ => return Err(invalid("Not a pcap file")),
+    };
+    let linktype = read_u32(&data[20..24], little_endian);
+    let resolution = if nanos { 1e-9 } else { 1e-6 };
+
+    let mut reports = vec![];
+    let mut start = None;
+    let mut offset = 24;
+    while offset < data.len() {
+        let record = &data[offset..];
+        if record.len() < 16 {
+            return Err(invalid("Truncated pcap record"));
+        }
+        let time = read_u32(&record[0..4], little_endian) as f64
+            + read_u32(&record[4..8], little_endian) as f64 * resolution;
+        let incl_len = read_u32(&record[8..12], little_endian) as usize;
+        let frame = record
+            .get(16..16 + incl_len)
+            .ok_or_else(|| invalid("Truncated pcap record"))?;
+        let start = *start.get_or_insert(time);
+        if let Some(report) = usb_payload(linktype, frame, little_endian)
+            .and_then(|payload| parse_report(time - start, payload))
+        {
+            reports.push(report);
+        }
+        offset += 16 + incl_len;
+    }
+    Ok(reports)
+}
+
+/// Read a pcapng file, the default format of Wireshark and USBPcap.
+pub fn read_pcapng(data: &[u8]) -> Result<Vec<CapturedReport>> {
+    const SECTION_HEADER: u32 = 0x0a0d0d0a;
+    const INTERFACE_DESCRIPTION: u32 = 0x00000001;
+    const SIMPLE_PACKET: u32 = 0x00000003;
+    const ENHANCED_PACKET: u32 = 0x00000006;
+    const IF_TSRESOL: u16 = 9;
+
+    // (link type, timestamp resolution in seconds) of every interface in the section
+    let mut interfaces: Vec<(u32, f64)> = vec![];
+    let mut little_endian = true;
+    let mut reports = vec![];
+    let mut start = None;
+    let mut offset = 0;
+
+    while offset < data.len() {
+        let block = &data[offset..];
+        if block.len() < 12 {
+            return Err(invalid("Truncated pcapng block"));
+        }
+        if read_u32(&block[0..4], true) == SECTION_HEADER {
+            little_endian = match block[8..12] {
+                [0x4d, 0x3c, 0x2b, 0x1a] => true,
+                [0x1a, 0x2b, 0x3c, 0x4d] => false,
+                _ => return Err(invalid("Invalid pcapng byte order magic")),
+            };
+            interfaces.clear();
+        }
+        let block_type = read_u32(&block[0..4], little_endian);
+        let block_len = read_u32(&block[4..8], little_endian) as usize;
+        if block_len < 12 {
+            return Err(invalid("Invalid pcapng block length"));
+        }
+        if block.len() < block_len {
+            return Err(invalid("Truncated pcapng block"));
+        }
+        let body = &block[8..block_len - 4];
+
+        match block_type {
+            INTERFACE_DESCRIPTION if body.len() >= 8 => {
+                let linktype = read_u16(&body[0..2], little_endian) as u32;
+                let mut resolution = 1e-6;
+                let mut options = &body[8..];
+                while options.len() >= 4 {
+                    let code = read_u16(&options[0..2], little_endian);
+                    let len = read_u16(&options[2..4], little_endian) as usize;
+                    if code == IF_TSRESOL && len == 1 && options.len() > 4 {
+                        let value = options[4];
+                        resolution = match value & 0x80 {
+                            0 => 10f64.powi(-(value as i32)),
+                            _ => 2f64.powi(-((value & 0x7f) as i32)),
+                        };
+                    }
+                    options = options
+                        .get(4 + len.next_multiple_of(4)..)
+                        .unwrap_or_default();
+                }
+                interfaces.push((linktype, resolution));
+            }
+            ENHANCED_PACKET | SIMPLE_PACKET => {
+                let (interface, time, frame) = match block_type {
+                    ENHANCED_PACKET => {
+                        if body.len() < 20 {
+                            return Err(invalid("Truncated enhanced packet block"));
+                        }
+                        let timestamp = (read_u32(&body[4..8], little_endian) as u64) << 32
+                            | read_u32(&body[8..12], little_endian) as u64;
+                        let captured = read_u32(&body[12..16], little_endian) as usize;
+                        let frame = body
+                            .get(20..20 + captured)
+                            .ok_or_else(|| invalid("Truncated packet"))?;
+                        let interface = read_u32(&body[0..4], little_endian) as usize;
+                        (interface, Some(timestamp), frame)
+                    }
+                    _ => (0, None, body.get(4..).unwrap_or_default()),
+                };
+                let &(linktype, resolution) = interfaces
+                    .get(interface)
+                    .ok_or_else(|| invalid("Packet refers to an unknown interface"))?;
+                let time = time.map_or(0.0, |time| time as f64 * resolution);
+                let start = *start.get_or_insert(time);
+                if let Some(report) = usb_payload(linktype, frame, little_endian)
+                    .and_then(|payload| parse_report(time - start, payload))
+                {
+                    reports.push(report);
+                }
+            }
+            _ => (),
+        }
+        offset += block_len;
+    }
+    Ok(reports)
+}
+
+/// Read a capture file, the format is detected from its content.
+pub fn read_capture(path: impl AsRef<Path>) -> Result<Vec<CapturedReport>> {
+    let path = path.as_ref();
+    let data = std::fs::read(path)?;
+    match data.get(..4) {
+        Some([0x0a, 0x0d, 0x0d, 0x0a]) => read_pcapng(&data),
+        Some([0xd4, 0xc3, 0xb2, 0xa1] | [0x4d, 0x3c, 0xb2, 0xa1])
+        | Some([0xa1, 0xb2, 0xc3, 0xd4] | [0xa1, 0xb2, 0x3c, 0x4d]) => read_pcap(&data),
+        _ => read_csv(
+            &String::from_utf8(data).map_err(|_| invalid("Capture is neither pcap nor CSV"))?,
+        ),
+    }
+}
+
+/// Read annotations, one `<second> "<action>"` per line.
+pub fn read_annotations(content: &str) -> Result<Vec<Annotation>> {
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(n, line)| {
+            let (time, action) = line
+                .trim()
+                .split_once(char::is_whitespace)
+                .ok_or_else(|| invalid(format!("Missing action on line {}", n + 1)))?;
+            Ok(Annotation {
+                time: time
+                    .parse::<f64>()
+                    .map_err(|_| invalid(format!("Invalid time on line {}", n + 1)))?
+                    as u64,
+                action: action.trim().trim_matches('"').to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Join the reports with the annotations by the second they were captured in.
+pub fn action_table(
+    reports: &[CapturedReport],
+    annotations: &[Annotation],
+) -> Result<Vec<ActionRow>> {
+    annotations
+        .iter()
+        .try_fold(vec![], |mut table, annotation| {
+            let reports: Vec<_> = reports
+                .iter()
+                .filter(|report| report.time as u64 == annotation.time)
+                .collect();
+            if reports.is_empty() {
+                return Err(invalid(format!(
+                    "No reports captured at {}s for action {:?}",
+                    annotation.time, annotation.action
+                )));
+            }
+            for (i, report) in reports.into_iter().enumerate() {
+                table.push(ActionRow {
+                    action: match i {
+                        0 => annotation.action.clone(),
+                        _ => String::new(),
+                    },
+                    command: report.command,
+                    args: report.args.clone(),
+                    crc_mismatch: report.crc_mismatch,
+                });
+            }
+            Ok(table)
+        })
+}
+
+/// Render the table in the markdown format used by `data/README.md`, commands of reports with a
+/// wrong crc are marked `(bad crc)`.
+pub fn to_markdown(rows: &[ActionRow]) -> String {
+    let argc = rows.iter().map(|row| row.args.len()).max().unwrap_or(0);
+    let header: Vec<String> = ["action", "cmd", "argc"]
+        .into_iter()
+        .map(String::from)
+        .chain((0..argc).map(|i| format!("arg{}", i)))
+        .collect();
+    let cells: Vec<Vec<String>> = rows
+        .iter()
+        .map(|row| {
+            [
+                row.action.clone(),
+                match row.crc_mismatch {
+                    true => format!("{:04x} (bad crc)", row.command),
+                    false => format!("{:04x}", row.command),
+                },
+                row.args.len().to_string(),
+            ]
+            .into_iter()
+            .chain((0..argc).map(|i| {
+                row.args
+                    .get(i)
+                    .map(|arg| format!("{:02x}", arg))
+                    .unwrap_or_default()
+            }))
+            .collect()
+        })
+        .collect();
+
+    let widths: Vec<usize> = (0..header.len())
+        .map(|i| {
+            cells
+                .iter()
+                .map(|row| row[i].len())
+                .chain([header[i].len()])
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+    let line = |row: &[String]| {
+        row.iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!(" {:width$} |", cell, width = width))
+            .fold("|".to_string(), |line, cell| line + &cell)
+    };
+
+    let mut table = vec![line(&header)];
+    table.push(
+        widths
+            .iter()
+            .map(|width| format!("{}|", "-".repeat(width + 2)))
+            .fold("|".to_string(), |line, cell| line + &cell),
+    );
+    table.extend(cells.iter().map(|row| line(row)));
+    table.join("\n")
+}
@@ -1,3 +1,4 @@
+use crate::commands::{self, execute, RazerCommand};
 use crate::device::Device;
 use crate::packet::Packet;
 use crate::types::{
@@ -5,44 +6,56 @@ use crate::types::{
     MaxFanSpeedMode, PerfMode,
 };
 
-use anyhow::{bail, ensure, Result};
+use crate::{Error, Result};
 
-fn _send_command(device: &Device, command: u16, args: &[u8]) -> Result<Packet> {
-    let response = device.send(Packet::new(command, args))?;
-    ensure!(response.get_args().starts_with(args));
-    Ok(response)
+// the checks are shared with `AsyncDevice`, which sends the same commands
+pub(crate) fn ensure_custom(perf_mode: PerfMode) -> Result<()> {
+    match perf_mode {
+        PerfMode::Custom => Ok(()),
+        _ => Err(Error::InvalidState(format!(
+            "Performance mode must be {:?}",
+            PerfMode::Custom
+        ))),
+    }
 }
 
-fn _set_perf_mode(device: &Device, perf_mode: PerfMode, fan_mode: FanMode) -> Result<()> {
+pub(crate) fn ensure_manual(fan_mode: FanMode) -> Result<()> {
+    match fan_mode {
+        FanMode::Manual => Ok(()),
+        _ => Err(Error::InvalidState(format!(
+            "Fan mode must be set to {:?}",
+            FanMode::Manual
+        ))),
+    }
+}
 
-    [1, 2].into_iter().try_for_each(|zone| {
-        _send_command(
-            device,
-            0x0d02,
-            &[0x01, zone, perf_mode as u8, fan_mode as u8],
-        )
-        .map(|_| ())
-    })
+// both fan zones are set to the same modes
+pub(crate) fn ensure_same_modes(
+    r1: (PerfMode, FanMode),
+    r2: (PerfMode, FanMode),
+) -> Result<(PerfMode, FanMode)> {
+    if r1 != r2 {
+        return Err(Error::InvalidState(format!(
+            "Modes do not match: r1 = {:?}, r2 = {:?}",
+            r1, r2
+        )));
+    }
+    Ok(r1)
 }
 
-fn _set_boost(device: &Device, cluster: Cluster, boost: u8) -> Result<()> {
-    let args = &[0x01, cluster as u8, boost];
-    ensure!(
-        get_perf_mode(device)?.0 == PerfMode::Custom,
-        "Performance mode must be {:?}",
-        PerfMode::Custom
-    );
-    ensure!(device
-        .send(Packet::new(0x0d07, args))?
-        .get_args()
-        .starts_with(args));
-    Ok(())
+fn ensure_custom_perf_mode(device: &Device) -> Result<()> {
+    ensure_custom(get_perf_mode(device)?.0)
+}
+
+fn _set_perf_mode(device: &Device, perf_mode: PerfMode, fan_mode: FanMode) -> Result<()> {
+    [FanZone::Zone1, FanZone::Zone2]
+        .into_iter()
+        .try_for_each(|zone| execute::<commands::SetPerfMode>(device, &(zone, perf_mode, fan_mode)))
 }
 
-fn _get_boost(device: &Device, cluster: Cluster) -> Result<u8> {
-    let response = device.send(Packet::new(0x0d87, &[0, cluster as u8, 0]))?;
-    ensure!(response.get_args()[1] == cluster as u8);
-    Ok(response.get_args()[2])
+fn _set_boost(device: &Device, cluster: Cluster, boost: u8) -> Result<()> {
+    ensure_custom_perf_mode(device)?;
+    execute::<commands::SetBoost>(device, &(cluster, boost))
 }
 
 pub fn set_perf_mode(device: &Device, perf_mode: PerfMode) -> Result<()> {
@@ -50,28 +63,9 @@ pub fn set_perf_mode(device: &Device, perf_mode: PerfMode) -> Result<()> {
 }
 
 pub fn get_perf_mode(device: &Device) -> Result<(PerfMode, FanMode)> {
-    let [r1, r2]: [Result<(PerfMode, FanMode)>; 2] = [1, 2].map(|zone| {
-        let response = device.send(Packet::new(0x0d82, &[0, zone, 0, 0]))?;
-        Ok((
-            PerfMode::try_from(response.get_args()[2])?,
-            FanMode::try_from(response.get_args()[3])?,
-        ))
-    });
-
-    ensure!(
-        r1.is_ok() && r2.is_ok(),
-        "Failed to get performance mode and fan mode: r1 = {:?}, r2 = {:?}",
-        r1,
-        r2
-    );
-
-    let r1 = r1?;
-    let r2 = r2?;
-
-    //let r1 = r1?;
-    ensure!(r1 == r2, "Modes do not match: r1 = {:?}, r2 = {:?}", r1, r2);
-
-    Ok(r1)
+    let r1 = execute::<commands::GetPerfMode>(device, &FanZone::Zone1)?;
+    let r2 = execute::<commands::GetPerfMode>(device, &FanZone::Zone2)?;
+    ensure_same_modes(r1, r2)
 }
 
 pub fn set_cpu_boost(device: &Device, boost: CpuBoost) -> Result<()> {
@@ -83,59 +77,57 @@ pub fn set_gpu_boost(device: &Device, boost: GpuBoost) -> Result<()> {
 }
 
 pub fn get_cpu_boost(device: &Device) -> Result<CpuBoost> {
-    CpuBoost::try_from(_get_boost(device, Cluster::Cpu)?)
+    CpuBoost::try_from(execute::<commands::GetBoost>(device, &Cluster::Cpu)?)
 }
 
 pub fn get_gpu_boost(device: &Device) -> Result<GpuBoost> {
-    GpuBoost::try_from(_get_boost(device, Cluster::Gpu)?)
+    GpuBoost::try_from(execute::<commands::GetBoost>(device, &Cluster::Gpu)?)
 }
 
 pub fn set_fan_rpm(device: &Device, rpm: u16, check_mode: bool) -> Result<()> {
-    ensure!((0..=5500).contains(&rpm));
+    // fail on the rpm before touching the device
+    commands::SetFanRpm::encode(&(FanZone::Zone1, rpm))?;
     if check_mode {
-        ensure!(
-            matches!(get_perf_mode(device)?, (_, FanMode::Manual)),
-            "Fan mode must be set to {:?}",
-            FanMode::Manual
-        );
+        ensure_manual(get_perf_mode(device)?.1)?;
     }
     [FanZone::Zone1, FanZone::Zone2]
         .into_iter()
-        .try_for_each(|zone| {
-            _send_command(device, 0x0d01, &[0, zone as u8, (rpm / 100) as u8]).map(|_| ())
-        })
+        .try_for_each(|zone| execute::<commands::SetFanRpm>(device, &(zone, rpm)))
 }
 
 pub fn get_fan_rpm(device: &Device, fan_zone: FanZone) -> Result<u16> {
-    let response = device.send(Packet::new(0x0d81, &[0, fan_zone as u8, 0]))?;
-    ensure!(response.get_args()[1] == fan_zone as u8);
-    Ok(response.get_args()[2] as u16 * 100)
+    execute::<commands::GetFanRpm>(device, &fan_zone)
 }
 
 pub fn get_fan_actual_rpm(device: &Device, fan_zone: FanZone) -> Result<u16> {
-    let response = device.send(Packet::new(0x0d88, &[0, fan_zone as u8, 0]))?;
-    ensure!(response.get_args()[1] == fan_zone as u8);
-    Ok(response.get_args()[2] as u16 * 100)
+    execute::<commands::GetFanActualRpm>(device, &fan_zone)
+}
+
+// `Packet::new` panics on arguments that don't fit, they may come from the user
+pub(crate) fn ensure_fits(args: &[u8]) -> Result<()> {
+    match args.len() <= Packet::MAX_ARGS {
+        true => Ok(()),
+        false => Err(Error::InvalidArgument(format!(
+            "{} bytes of arguments don't fit in a report, at most {} do",
+            args.len(),
+            Packet::MAX_ARGS
+        ))),
+    }
 }
 
-
 pub fn send_command(device: &Device, command: u16, args: &[u8]) -> Result<Packet> {
+    ensure_fits(args)?;
     let response = device.send(Packet::new(command, args))?;
     Ok(response)
 }
 
-
 pub fn set_max_fan_speed_mode(device: &Device, mode: MaxFanSpeedMode) -> Result<()> {
-    ensure!(
-        get_perf_mode(device)?.0 == PerfMode::Custom,
-        "Performance mode must be {:?}",
-        PerfMode::Custom
-    );
-    _send_command(device, 0x070f, &[mode as u8]).map(|_| ())
+    ensure_custom_perf_mode(device)?;
+    execute::<commands::SetMaxFanSpeedMode>(device, &mode)
 }
 
 pub fn get_max_fan_speed_mode(device: &Device) -> Result<MaxFanSpeedMode> {
-    device.send(Packet::new(0x078f, &[0]))?.get_args()[0].try_into()
+    execute::<commands::GetMaxFanSpeedMode>(device, &())
 }
 
 pub fn set_fan_mode(device: &Device, mode: FanMode) -> Result<()> {
@@ -143,97 +135,57 @@ pub fn set_fan_mode(device: &Device, mode: FanMode) -> Result<()> {
 }
 
 pub fn custom_command(device: &Device, command: u16, args: &[u8]) -> Result<()> {
+    ensure_fits(args)?;
     let report = Packet::new(command, args);
-    println!("Report   {:?}", report);
+    println!("Report   {}", report);
     let response = device.send(report)?;
-    println!("Response {:?}", response);
+    println!("Response {}", response);
+    println!(
+        "Args     {:02x?}",
+        response
+            .get_args()
+            .iter()
+            .take(response.data_size())
+            .collect::<Vec<_>>()
+    );
     Ok(())
 }
 
-fn _set_logo_power(device: &Device, mode: LogoMode) -> Result<Packet> {
-    match mode {
-        LogoMode::Off => _send_command(device, 0x0300, &[1, 4, 0]),
-        LogoMode::Static | LogoMode::Breathing => _send_command(device, 0x0300, &[1, 4, 1]),
-    }
-}
-
-fn _set_logo_mode(device: &Device, mode: LogoMode) -> Result<Packet> {
-    match mode {
-        LogoMode::Static => _send_command(device, 0x0302, &[1, 4, 0]),
-        LogoMode::Breathing => _send_command(device, 0x0302, &[1, 4, 2]),
-        _ => bail!("Invalid logo mode"),
-    }
-}
-
-fn _get_logo_power(device: &Device) -> Result<bool> {
-    match device.send(Packet::new(0x0380, &[1, 4, 0]))?.get_args()[2] {
-        0 => Ok(false),
-        1 => Ok(true),
-        _ => bail!("Invalid logo power state"),
-    }
-}
-
-fn _get_logo_mode(device: &Device) -> Result<LogoMode> {
-    match device.send(Packet::new(0x0382, &[1, 4, 0]))?.get_args()[2] {
-        0 => Ok(LogoMode::Static),
-        2 => Ok(LogoMode::Breathing),
-        _ => bail!("Invalid logo power state"),
-    }
-}
-
 pub fn get_logo_mode(device: &Device) -> Result<LogoMode> {
-    let power = _get_logo_power(device)?;
+    let power = execute::<commands::GetLogoPower>(device, &())?;
     match power {
-        true => _get_logo_mode(device),
+        true => execute::<commands::GetLogoMode>(device, &()),
         false => Ok(LogoMode::Off),
     }
 }
 
 pub fn set_logo_mode(device: &Device, mode: LogoMode) -> Result<()> {
     if mode != LogoMode::Off {
-        _set_logo_mode(device, mode)?;
+        execute::<commands::SetLogoMode>(device, &mode)?;
     }
-    _set_logo_power(device, mode)?;
-    Ok(())
+    execute::<commands::SetLogoPower>(device, &(mode != LogoMode::Off))
 }
 
 pub fn get_keyboard_brightness(device: &Device) -> Result<u8> {
-    let response = device.send(Packet::new(0x0383, &[1, 5, 0]))?;
-    ensure!(response.get_args()[1] == 5);
-    Ok(response.get_args()[2])
+    execute::<commands::GetKeyboardBrightness>(device, &())
 }
 
 pub fn set_keyboard_brightness(device: &Device, brightness: u8) -> Result<()> {
-    let args = &[1, 5, brightness];
-    ensure!(device
-        .send(Packet::new(0x0303, args))?
-        .get_args()
-        .starts_with(args));
-    Ok(())
+    execute::<commands::SetKeyboardBrightness>(device, &brightness)
 }
 
 pub fn get_lights_always_on(device: &Device) -> Result<LightsAlwaysOn> {
-    device.send(Packet::new(0x0084, &[0, 0]))?.get_args()[0].try_into()
+    execute::<commands::GetLightsAlwaysOn>(device, &())
 }
 
 pub fn set_lights_always_on(device: &Device, lights_always_on: LightsAlwaysOn) -> Result<()> {
-    let args = &[lights_always_on as u8, 0];
-    ensure!(device
-        .send(Packet::new(0x0004, args))?
-        .get_args()
-        .starts_with(args));
-    Ok(())
+    execute::<commands::SetLightsAlwaysOn>(device, &lights_always_on)
 }
 
 pub fn get_battery_care(device: &Device) -> Result<BatteryCare> {
-    device.send(Packet::new(0x0792, &[0]))?.get_args()[0].try_into()
+    execute::<commands::GetBatteryCare>(device, &())
 }
 
 pub fn set_battery_care(device: &Device, mode: BatteryCare) -> Result<()> {
-    let args = &[mode as u8];
-    ensure!(device
-        .send(Packet::new(0x0712, args))?
-        .get_args()
-        .starts_with(args));
-    Ok(())
+    execute::<commands::SetBatteryCare>(device, &mode)
 }
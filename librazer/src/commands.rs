@@ -0,0 +1,423 @@
+use crate::device::Device;
+use crate::packet::Packet;
+use crate::types::{
+    BatteryCare, Cluster, FanMode, FanZone, LightsAlwaysOn, LogoMode, MaxFanSpeedMode, PerfMode,
+};
+use crate::{Error, Result};
+
+/// How a response is checked against its report before it is decoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Validation {
+    /// The response starts with the arguments of the report, e.g. setters.
+    Echo,
+    /// The response repeats the selector argument (zone, cluster, led) at this index.
+    Selector(usize),
+    /// Only the header is checked, e.g. getters without arguments.
+    Header,
+}
+
+impl Validation {
+    pub(crate) fn check(&self, report: &Packet, response: &Packet) -> Result<()> {
+        let (ok, reason) = match *self {
+            Validation::Echo => (
+                response
+                    .get_args()
+                    .starts_with(&report.get_args()[..report.data_size()]),
+                "arguments are not echoed",
+            ),
+            Validation::Selector(index) => (
+                response.get_args()[index] == report.get_args()[index],
+                "selector differs",
+            ),
+            Validation::Header => (true, ""),
+        };
+        match ok {
+            true => Ok(()),
+            false => Err(Error::ResponseMismatch {
+                command: report.command(),
+                reason,
+                report: report.into(),
+                response: response.into(),
+            }),
+        }
+    }
+}
+
+/// A command of the EC with typed arguments and response.
+pub trait RazerCommand {
+    const COMMAND: u16;
+    const VALIDATION: Validation;
+    type Request;
+    type Response;
+
+    /// Arguments of the report, fails on values the EC does not take.
+    fn encode(request: &Self::Request) -> Result<Vec<u8>>;
+
+    /// Response from the arguments of a response that passed `VALIDATION`.
+    fn decode(args: &[u8]) -> Result<Self::Response>;
+}
+
+/// Send `C` and decode its response, e.g. `execute::<GetFanRpm>(device, &FanZone::Zone1)`.
+pub fn execute<C: RazerCommand>(device: &Device, request: &C::Request) -> Result<C::Response> {
+    let report = Packet::new(C::COMMAND, &C::encode(request)?);
+    let response = device.send(report.clone())?;
+    C::VALIDATION.check(&report, &response)?;
+    C::decode(response.get_args())
+}
+
+fn decode_bool(value: u8, kind: &'static str) -> Result<bool> {
+    match value {
+        0 => Ok(false),
+        1 => Ok(true),
+        value => Err(Error::InvalidValue { kind, value }),
+    }
+}
+
+pub struct SetPerfMode;
+
+impl RazerCommand for SetPerfMode {
+    const COMMAND: u16 = 0x0d02;
+    const VALIDATION: Validation = Validation::Echo;
+    type Request = (FanZone, PerfMode, FanMode);
+    type Response = ();
+
+    fn encode(&(zone, perf_mode, fan_mode): &Self::Request) -> Result<Vec<u8>> {
+        Ok(vec![0x01, zone as u8, perf_mode as u8, fan_mode as u8])
+    }
+
+    fn decode(_: &[u8]) -> Result<()> {
+        Ok(())
+    }
+}
+
+pub struct GetPerfMode;
+
+impl RazerCommand for GetPerfMode {
+    const COMMAND: u16 = 0x0d82;
+    const VALIDATION: Validation = Validation::Selector(1);
+    type Request = FanZone;
+    type Response = (PerfMode, FanMode);
+
+    fn encode(&zone: &FanZone) -> Result<Vec<u8>> {
+        Ok(vec![0, zone as u8, 0, 0])
+    }
+
+    fn decode(args: &[u8]) -> Result<(PerfMode, FanMode)> {
+        Ok((args[2].try_into()?, args[3].try_into()?))
+    }
+}
+
+/// CPU or GPU boost, `CpuBoost` or `GpuBoost` as u8.
+pub struct SetBoost;
+
+impl RazerCommand for SetBoost {
+    const COMMAND: u16 = 0x0d07;
+    const VALIDATION: Validation = Validation::Echo;
+    type Request = (Cluster, u8);
+    type Response = ();
+
+    fn encode(&(cluster, boost): &Self::Request) -> Result<Vec<u8>> {
+        Ok(vec![0x01, cluster as u8, boost])
+    }
+
+    fn decode(_: &[u8]) -> Result<()> {
+        Ok(())
+    }
+}
+
+pub struct GetBoost;
+
+impl RazerCommand for GetBoost {
+    const COMMAND: u16 = 0x0d87;
+    const VALIDATION: Validation = Validation::Selector(1);
+    type Request = Cluster;
+    type Response = u8;
+
+    fn encode(&cluster: &Cluster) -> Result<Vec<u8>> {
+        Ok(vec![0, cluster as u8, 0])
+    }
+
+    fn decode(args: &[u8]) -> Result<u8> {
+        Ok(args[2])
+    }
+}
+
+/// Requested fan speed in RPM, the EC takes it in units of 100 RPM.
+pub struct SetFanRpm;
+
+impl RazerCommand for SetFanRpm {
+    const COMMAND: u16 = 0x0d01;
+    const VALIDATION: Validation = Validation::Echo;
+    type Request = (FanZone, u16);
+    type Response = ();
+
+    fn encode(&(zone, rpm): &Self::Request) -> Result<Vec<u8>> {
+        if !(0..=5500).contains(&rpm) {
+            return Err(Error::InvalidArgument(format!(
+                "Fan rpm {} is out of range [0, 5500]",
+                rpm
+            )));
+        }
+        Ok(vec![0, zone as u8, (rpm / 100) as u8])
+    }
+
+    fn decode(_: &[u8]) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Requested fan speed in RPM.
+pub struct GetFanRpm;
+
+impl RazerCommand for GetFanRpm {
+    const COMMAND: u16 = 0x0d81;
+    const VALIDATION: Validation = Validation::Selector(1);
+    type Request = FanZone;
+    type Response = u16;
+
+    fn encode(&zone: &FanZone) -> Result<Vec<u8>> {
+        Ok(vec![0, zone as u8, 0])
+    }
+
+    fn decode(args: &[u8]) -> Result<u16> {
+        Ok(args[2] as u16 * 100)
+    }
+}
+
+/// Measured fan speed in RPM.
+pub struct GetFanActualRpm;
+
+impl RazerCommand for GetFanActualRpm {
+    const COMMAND: u16 = 0x0d88;
+    const VALIDATION: Validation = Validation::Selector(1);
+    type Request = FanZone;
+    type Response = u16;
+
+    fn encode(&zone: &FanZone) -> Result<Vec<u8>> {
+        Ok(vec![0, zone as u8, 0])
+    }
+
+    fn decode(args: &[u8]) -> Result<u16> {
+        Ok(args[2] as u16 * 100)
+    }
+}
+
+pub struct SetMaxFanSpeedMode;
+
+impl RazerCommand for SetMaxFanSpeedMode {
+    const COMMAND: u16 = 0x070f;
+    const VALIDATION: Validation = Validation::Echo;
+    type Request = MaxFanSpeedMode;
+    type Response = ();
+
+    fn encode(&mode: &MaxFanSpeedMode) -> Result<Vec<u8>> {
+        Ok(vec![mode as u8])
+    }
+
+    fn decode(_: &[u8]) -> Result<()> {
+        Ok(())
+    }
+}
+
+pub struct GetMaxFanSpeedMode;
+
+impl RazerCommand for GetMaxFanSpeedMode {
+    const COMMAND: u16 = 0x078f;
+    const VALIDATION: Validation = Validation::Header;
+    type Request = ();
+    type Response = MaxFanSpeedMode;
+
+    fn encode(_: &()) -> Result<Vec<u8>> {
+        Ok(vec![0])
+    }
+
+    fn decode(args: &[u8]) -> Result<MaxFanSpeedMode> {
+        args[0].try_into()
+    }
+}
+
+/// Lid logo on or off.
+pub struct SetLogoPower;
+
+impl RazerCommand for SetLogoPower {
+    const COMMAND: u16 = 0x0300;
+    const VALIDATION: Validation = Validation::Echo;
+    type Request = bool;
+    type Response = ();
+
+    fn encode(&power: &bool) -> Result<Vec<u8>> {
+        Ok(vec![1, 4, power as u8])
+    }
+
+    fn decode(_: &[u8]) -> Result<()> {
+        Ok(())
+    }
+}
+
+pub struct GetLogoPower;
+
+impl RazerCommand for GetLogoPower {
+    const COMMAND: u16 = 0x0380;
+    const VALIDATION: Validation = Validation::Selector(1);
+    type Request = ();
+    type Response = bool;
+
+    fn encode(_: &()) -> Result<Vec<u8>> {
+        Ok(vec![1, 4, 0])
+    }
+
+    fn decode(args: &[u8]) -> Result<bool> {
+        decode_bool(args[2], "logo power")
+    }
+}
+
+/// Effect of the powered lid logo, `LogoMode::Off` is `SetLogoPower`.
+pub struct SetLogoMode;
+
+impl RazerCommand for SetLogoMode {
+    const COMMAND: u16 = 0x0302;
+    const VALIDATION: Validation = Validation::Echo;
+    type Request = LogoMode;
+    type Response = ();
+
+    fn encode(&mode: &LogoMode) -> Result<Vec<u8>> {
+        match mode {
+            LogoMode::Static => Ok(vec![1, 4, 0]),
+            LogoMode::Breathing => Ok(vec![1, 4, 2]),
+            LogoMode::Off => Err(Error::InvalidArgument(format!(
+                "Invalid logo mode {:?}",
+                mode
+            ))),
+        }
+    }
+
+    fn decode(_: &[u8]) -> Result<()> {
+        Ok(())
+    }
+}
+
+pub struct GetLogoMode;
+
+impl RazerCommand for GetLogoMode {
+    const COMMAND: u16 = 0x0382;
+    const VALIDATION: Validation = Validation::Selector(1);
+    type Request = ();
+    type Response = LogoMode;
+
+    fn encode(_: &()) -> Result<Vec<u8>> {
+        Ok(vec![1, 4, 0])
+    }
+
+    fn decode(args: &[u8]) -> Result<LogoMode> {
+        match args[2] {
+            0 => Ok(LogoMode::Static),
+            2 => Ok(LogoMode::Breathing),
+            value => Err(Error::InvalidValue {
+                kind: "LogoMode",
+                value,
+            }),
+        }
+    }
+}
+
+pub struct SetKeyboardBrightness;
+
+impl RazerCommand for SetKeyboardBrightness {
+    const COMMAND: u16 = 0x0303;
+    const VALIDATION: Validation = Validation::Echo;
+    type Request = u8;
+    type Response = ();
+
+    fn encode(&brightness: &u8) -> Result<Vec<u8>> {
+        Ok(vec![1, 5, brightness])
+    }
+
+    fn decode(_: &[u8]) -> Result<()> {
+        Ok(())
+    }
+}
+
+pub struct GetKeyboardBrightness;
+
+impl RazerCommand for GetKeyboardBrightness {
+    const COMMAND: u16 = 0x0383;
+    const VALIDATION: Validation = Validation::Selector(1);
+    type Request = ();
+    type Response = u8;
+
+    fn encode(_: &()) -> Result<Vec<u8>> {
+        Ok(vec![1, 5, 0])
+    }
+
+    fn decode(args: &[u8]) -> Result<u8> {
+        Ok(args[2])
+    }
+}
+
+pub struct SetLightsAlwaysOn;
+
+impl RazerCommand for SetLightsAlwaysOn {
+    const COMMAND: u16 = 0x0004;
+    const VALIDATION: Validation = Validation::Echo;
+    type Request = LightsAlwaysOn;
+    type Response = ();
+
+    fn encode(&lights_always_on: &LightsAlwaysOn) -> Result<Vec<u8>> {
+        Ok(vec![lights_always_on as u8, 0])
+    }
+
+    fn decode(_: &[u8]) -> Result<()> {
+        Ok(())
+    }
+}
+
+pub struct GetLightsAlwaysOn;
+
+impl RazerCommand for GetLightsAlwaysOn {
+    const COMMAND: u16 = 0x0084;
+    const VALIDATION: Validation = Validation::Header;
+    type Request = ();
+    type Response = LightsAlwaysOn;
+
+    fn encode(_: &()) -> Result<Vec<u8>> {
+        Ok(vec![0, 0])
+    }
+
+    fn decode(args: &[u8]) -> Result<LightsAlwaysOn> {
+        args[0].try_into()
+    }
+}
+
+pub struct SetBatteryCare;
+
+impl RazerCommand for SetBatteryCare {
+    const COMMAND: u16 = 0x0712;
+    const VALIDATION: Validation = Validation::Echo;
+    type Request = BatteryCare;
+    type Response = ();
+
+    fn encode(&mode: &BatteryCare) -> Result<Vec<u8>> {
+        Ok(vec![mode as u8])
+    }
+
+    fn decode(_: &[u8]) -> Result<()> {
+        Ok(())
+    }
+}
+
+pub struct GetBatteryCare;
+
+impl RazerCommand for GetBatteryCare {
+    const COMMAND: u16 = 0x0792;
+    const VALIDATION: Validation = Validation::Header;
+    type Request = ();
+    type Response = BatteryCare;
+
+    fn encode(_: &()) -> Result<Vec<u8>> {
+        Ok(vec![0])
+    }
+
+    fn decode(args: &[u8]) -> Result<BatteryCare> {
+        args[0].try_into()
+    }
+}
@@ -1,15 +1,66 @@
 use crate::feature;
+use crate::packet::Packet;
+use crate::policy::{DevicePolicy, PolicyOverrides};
+use crate::protocol::Protocol;
+use crate::quirks::{self, HeaderField, Quirk};
+use crate::{Error, Result};
+
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 
 // model_number_prefix shall conform to https://mysupport.razer.com/app/answers/detail/a_id/5481
+// and may contain `?` and `*` wildcards, see `matching::matches_model`
 #[derive(Debug, Clone)]
 pub struct Descriptor {
     pub model_number_prefix: &'static str,
     pub name: &'static str,
     pub pid: u16,
     pub features: &'static [&'static str],
-    pub init_cmds: &'static [u16],
+    /// Sent in order whenever the device is opened, see `Device::init`.
+    pub init: &'static [InitStep],
+    /// Timings of the EC, overridable per device.
+    pub policy: DevicePolicy,
+    /// Transaction id strategy and protocol type of the reports.
+    pub protocol: Protocol,
+    /// Deviations of the EC from the protocol, `quirks::DEFAULT` unless the model has more.
+    pub quirks: &'static [Quirk],
+}
+
+/// Report sent to bring the EC into a known state, e.g. before the first command of a session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InitStep {
+    pub command: u16,
+    pub args: &'static [u8],
+    /// Arguments the response has to start with, any successful response is fine if `None`.
+    pub expect: Option<&'static [u8]>,
+}
+
+impl InitStep {
+    pub const fn new(command: u16, args: &'static [u8]) -> InitStep {
+        InitStep {
+            command,
+            args,
+            expect: None,
+        }
+    }
+
+    pub const fn expect(mut self, response: &'static [u8]) -> InitStep {
+        self.expect = Some(response);
+        self
+    }
 }
 
+// start up sequence of the Blade 16 (2025)
+const BLADE_16_2025_INIT: &[InitStep] = &[
+    InitStep::new(0x0081, &[0, 0, 0, 0]),
+    InitStep::new(0x0086, &[0, 0, 0, 0]),
+    InitStep::new(0x0f90, &[0, 0, 0, 0]),
+    InitStep::new(0x0086, &[0, 0, 0, 0]),
+    InitStep::new(0x0f10, &[0, 0, 0, 0]),
+    InitStep::new(0x0087, &[0, 0, 0, 0]),
+];
+
 pub const SUPPORTED: &[Descriptor] = &[
     Descriptor {
         model_number_prefix: "RZ09-0483T",
@@ -23,7 +74,10 @@ pub const SUPPORTED: &[Descriptor] = &[
             "lights-always-on",
             "perf",
         ],
-        init_cmds : &[],
+        init: &[],
+        policy: DevicePolicy::DEFAULT,
+        protocol: Protocol::DEFAULT,
+        quirks: quirks::DEFAULT,
     },
     Descriptor {
         model_number_prefix: "RZ09-0482X",
@@ -36,7 +90,10 @@ pub const SUPPORTED: &[Descriptor] = &[
             "lights-always-on",
             "perf",
         ],
-        init_cmds : &[],
+        init: &[],
+        policy: DevicePolicy::DEFAULT,
+        protocol: Protocol::DEFAULT,
+        quirks: quirks::DEFAULT,
     },
     Descriptor {
         model_number_prefix: "RZ09-0510S",
@@ -50,7 +107,10 @@ pub const SUPPORTED: &[Descriptor] = &[
             "lights-always-on",
             "perf",
         ],
-        init_cmds : &[],
+        init: &[],
+        policy: DevicePolicy::DEFAULT,
+        protocol: Protocol::DEFAULT,
+        quirks: quirks::DEFAULT,
     },
     Descriptor {
         model_number_prefix: "RZ09-05289",
@@ -64,7 +124,10 @@ pub const SUPPORTED: &[Descriptor] = &[
             "lights-always-on",
             "perf",
         ],
-        init_cmds : &[0x0081,0x0086,0x0f90,0x0086,0x0f10,0x0087],
+        init: BLADE_16_2025_INIT,
+        policy: DevicePolicy::DEFAULT,
+        protocol: Protocol::DEFAULT,
+        quirks: quirks::DEFAULT,
     },
     Descriptor {
         model_number_prefix: "RZ09-05288",
@@ -78,7 +141,10 @@ pub const SUPPORTED: &[Descriptor] = &[
             "lights-always-on",
             "perf",
         ],
-        init_cmds : &[0x0081,0x0086,0x0f90,0x0086,0x0f10,0x0087],
+        init: BLADE_16_2025_INIT,
+        policy: DevicePolicy::DEFAULT,
+        protocol: Protocol::DEFAULT,
+        quirks: quirks::DEFAULT,
     },
     Descriptor {
         model_number_prefix: "RZ09-05286",
@@ -92,7 +158,10 @@ pub const SUPPORTED: &[Descriptor] = &[
             "lights-always-on",
             "perf",
         ],
-        init_cmds : &[0x0081,0x0086,0x0f90,0x0086,0x0f10,0x0087],
+        init: BLADE_16_2025_INIT,
+        policy: DevicePolicy::DEFAULT,
+        protocol: Protocol::DEFAULT,
+        quirks: quirks::DEFAULT,
     },
     Descriptor {
         model_number_prefix: "RZ09-0421N",
@@ -106,8 +175,11 @@ pub const SUPPORTED: &[Descriptor] = &[
             "lights-always-on",
             "perf",
         ],
-        init_cmds : &[],
-    }
+        init: &[],
+        policy: DevicePolicy::DEFAULT,
+        protocol: Protocol::DEFAULT,
+        quirks: quirks::DEFAULT,
+    },
 ];
 
 const _VALIDATE_FEATURES: () = {
@@ -115,3 +187,252 @@ const _VALIDATE_FEATURES: () = {
         feature::validate_features(device.features);
     }}
 };
+
+/// Descriptor file, e.g. `~/.config/razer-ctl/descriptors/blade-18.toml`:
+///
+/// ```toml
+/// model_number_prefix = "RZ09-0509X"
+/// name = "Razer Blade 18 (2024)"
+/// pid = 0x02a0
+/// features = ["battery-care", "fan", "kbd-backlight", "lights-always-on", "perf"]
+/// init = [{ command = 0x0081, args = [0, 0, 0, 0] }]
+/// timing = { read_delay_us = 5000 }
+/// protocol = { transaction_id = { fixed = 0x1f }, protocol_type = 0 }
+/// quirks = [{ quirk = "extra-delay", command = 0x0d82, delay_us = 5000 }]
+/// ```
+///
+/// JSON files take the same keys, with decimal numbers. Quirks are added to `quirks::DEFAULT`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DescriptorFile {
+    model_number_prefix: String,
+    name: String,
+    pid: u16,
+    features: Vec<String>,
+    #[serde(default)]
+    init: Vec<InitStepFile>,
+    #[serde(default)]
+    timing: PolicyOverrides,
+    #[serde(default)]
+    protocol: Protocol,
+    #[serde(default)]
+    quirks: Vec<QuirkFile>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct InitStepFile {
+    command: u16,
+    #[serde(default)]
+    args: Vec<u8>,
+    expect: Option<Vec<u8>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "quirk", rename_all = "kebab-case", deny_unknown_fields)]
+enum QuirkFile {
+    ToleratedMismatch { command: u16, field: HeaderField },
+    ExtraDelay { command: u16, delay_us: u64 },
+    ResponseClass { command: u16, class: u8 },
+    ResponseOffset { command: u16, offset: usize },
+}
+
+impl From<QuirkFile> for Quirk {
+    fn from(quirk: QuirkFile) -> Quirk {
+        match quirk {
+            QuirkFile::ToleratedMismatch { command, field } => {
+                Quirk::ToleratedMismatch { command, field }
+            }
+            QuirkFile::ExtraDelay { command, delay_us } => Quirk::ExtraDelay {
+                command,
+                delay: std::time::Duration::from_micros(delay_us),
+            },
+            QuirkFile::ResponseClass { command, class } => Quirk::ResponseClass { command, class },
+            QuirkFile::ResponseOffset { command, offset } => {
+                Quirk::ResponseOffset { command, offset }
+            }
+        }
+    }
+}
+
+impl DescriptorFile {
+    // descriptors are loaded once and live as long as the built-in ones
+    fn into_descriptor(self) -> Result<Descriptor> {
+        // every model number starts with the empty prefix
+        if self.model_number_prefix.trim().is_empty() {
+            return Err(Error::InvalidData(
+                "model_number_prefix must not be empty".to_string(),
+            ));
+        }
+        // `Packet::new` can't take more
+        if let Some((step, init)) = self
+            .init
+            .iter()
+            .enumerate()
+            .find(|(_, init)| init.args.len() > Packet::MAX_ARGS)
+        {
+            return Err(Error::InvalidData(format!(
+                "init step {} ({:#06x}) has {} bytes of arguments, at most {} fit in a report",
+                step,
+                init.command,
+                init.args.len(),
+                Packet::MAX_ARGS
+            )));
+        }
+        let features = self
+            .features
+            .iter()
+            .map(|name| {
+                feature::ALL_FEATURES
+                    .iter()
+                    .find(|feature| **feature == name)
+                    .copied()
+                    .ok_or_else(|| {
+                        Error::InvalidData(format!(
+                            "Unknown feature {}, expected one of {:?}",
+                            name,
+                            feature::ALL_FEATURES
+                        ))
+                    })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Descriptor {
+            model_number_prefix: Box::leak(self.model_number_prefix.into_boxed_str()),
+            name: Box::leak(self.name.into_boxed_str()),
+            pid: self.pid,
+            features: Box::leak(features.into_boxed_slice()),
+            init: Box::leak(
+                self.init
+                    .into_iter()
+                    .map(|step| InitStep {
+                        command: step.command,
+                        args: Box::leak(step.args.into_boxed_slice()),
+                        expect: step
+                            .expect
+                            .map(|expect| &*Box::leak(expect.into_boxed_slice())),
+                    })
+                    .collect::<Vec<_>>()
+                    .into_boxed_slice(),
+            ),
+            policy: self.timing.apply(DevicePolicy::DEFAULT),
+            protocol: self.protocol,
+            quirks: Box::leak(
+                quirks::DEFAULT
+                    .iter()
+                    .copied()
+                    .chain(self.quirks.into_iter().map(Quirk::from))
+                    .collect::<Vec<_>>()
+                    .into_boxed_slice(),
+            ),
+        })
+    }
+}
+
+/// Load and validate a `.toml` or `.json` descriptor file.
+pub fn load(path: &Path) -> Result<Descriptor> {
+    let content = std::fs::read_to_string(path)?;
+    let file: DescriptorFile = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("toml") => toml::from_str(&content).map_err(|e| Error::InvalidData(e.to_string()))?,
+        Some("json") => {
+            serde_json::from_str(&content).map_err(|e| Error::InvalidData(e.to_string()))?
+        }
+        _ => {
+            return Err(Error::InvalidData(format!(
+                "{} is neither a .toml nor a .json file",
+                path.display()
+            )))
+        }
+    };
+    file.into_descriptor().map_err(|e| match e {
+        Error::InvalidData(message) => {
+            Error::InvalidData(format!("{}: {}", path.display(), message))
+        }
+        e => e,
+    })
+}
+
+/// Directories descriptor files are loaded from, in order of precedence:
+/// `$XDG_CONFIG_HOME/razer-ctl/descriptors` (`~/.config` if not set), the same under every
+/// entry of `$XDG_CONFIG_DIRS` (`/etc/xdg` if not set), then `/usr/local/share` and `/usr/share`.
+pub fn search_dirs() -> Vec<PathBuf> {
+    let env_dir = |name| {
+        std::env::var_os(name)
+            .filter(|value| !value.is_empty())
+            .map(PathBuf::from)
+    };
+    let config_home =
+        env_dir("XDG_CONFIG_HOME").or_else(|| env_dir("HOME").map(|home| home.join(".config")));
+    let config_dirs = std::env::var_os("XDG_CONFIG_DIRS")
+        .filter(|value| !value.is_empty())
+        .unwrap_or_else(|| "/etc/xdg".into());
+
+    config_home
+        .into_iter()
+        .chain(std::env::split_paths(&config_dirs))
+        .chain(["/usr/local/share".into(), "/usr/share".into()])
+        .map(|dir| dir.join("razer-ctl").join("descriptors"))
+        .collect()
+}
+
+/// Descriptor files found in `dirs`, e.g. `search_dirs()`, invalid ones are skipped with a
+/// warning.
+///
+/// A file takes precedence over the files of later directories with the same model number
+/// prefix, and over the built-in descriptor of that model.
+pub fn load_files(dirs: &[PathBuf]) -> Vec<Descriptor> {
+    let mut descriptors: Vec<Descriptor> = Vec::new();
+    for dir in dirs {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            continue;
+        };
+        let mut paths: Vec<_> = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                matches!(
+                    path.extension().and_then(|ext| ext.to_str()),
+                    Some("toml" | "json")
+                )
+            })
+            .collect();
+        paths.sort();
+
+        for path in paths {
+            match load(&path) {
+                Ok(descriptor) => {
+                    if descriptors
+                        .iter()
+                        .any(|loaded| loaded.model_number_prefix == descriptor.model_number_prefix)
+                    {
+                        log::debug!("{} is shadowed, skipping", path.display());
+                    } else {
+                        log::info!("Loaded {} from {}", descriptor.name, path.display());
+                        descriptors.push(descriptor);
+                    }
+                }
+                Err(e) => log::warn!("Skipping descriptor {}: {}", path.display(), e),
+            }
+        }
+    }
+    descriptors
+}
+
+/// Descriptor files of `dirs` followed by the built-in `SUPPORTED` models they don't override.
+pub fn supported_from(dirs: &[PathBuf]) -> Vec<Descriptor> {
+    let mut all = load_files(dirs);
+    for builtin in SUPPORTED {
+        if !all
+            .iter()
+            .any(|loaded| loaded.model_number_prefix == builtin.model_number_prefix)
+        {
+            all.push(builtin.clone());
+        }
+    }
+    all
+}
+
+/// `supported_from` the `search_dirs`, files are loaded on the first call.
+pub fn supported() -> &'static [Descriptor] {
+    static ALL: OnceLock<Vec<Descriptor>> = OnceLock::new();
+    ALL.get_or_init(|| supported_from(&search_dirs()))
+}
@@ -1,142 +1,518 @@
-use crate::descriptor::{Descriptor, SUPPORTED};
+use crate::command;
+use crate::descriptor::{self, Descriptor};
+use crate::lock::{DeviceLock, LockGuard};
+use crate::matching::{self, SystemInfo};
 use crate::packet::Packet;
+use crate::policy::DevicePolicy;
+use crate::probe::{self, Capabilities};
+use crate::protocol::TransactionId;
+use crate::quirks::{self, HeaderField, Quirk};
+use crate::transport::Transport;
 
-use anyhow::{anyhow, Context, Result};
+use crate::{Error, Result};
+
+use log::debug;
+use serde::Serialize;
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, BTreeSet};
 use std::{thread, time};
 
 pub struct Device {
-    device: hidapi::HidDevice,
+    transport: Box<dyn Transport>,
+    policy: DevicePolicy,
+    stats: RefCell<BTreeMap<u16, CommandStats>>,
+    // transaction id of the previous report
+    last_id: Cell<u8>,
+    lock: Option<DeviceLock>,
+    // how the HID interface was picked, `reconnect` picks it again
+    interface: Option<Interface>,
     pub info: Descriptor,
 }
 
-// Read the model id and clip to conform with https://mysupport.razer.com/app/answers/detail/a_id/5481
-#[cfg(target_os = "windows")]
-fn read_device_model() -> Result<String> {
-    let hklm = winreg::RegKey::predef(winreg::enums::HKEY_LOCAL_MACHINE);
-    let bios = hklm.open_subkey("HARDWARE\\DESCRIPTION\\System\\BIOS")?;
-    let system_sku: String = bios.get_value("SystemSKU")?;
-    Ok(system_sku.chars().take(10).collect())
+/// Counters of one command, for diagnostics.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CommandStats {
+    /// Number of `Device::send` calls.
+    pub calls: u32,
+    /// Reports sent again after Busy, Timeout or a stale response.
+    pub retries: u32,
+    pub failures: u32,
+    /// Time spent in `Device::send`, including retries.
+    pub total_latency: time::Duration,
+    pub max_latency: time::Duration,
 }
 
-#[cfg(target_os = "linux")]
-fn read_device_model() -> Result<String> {
-    let sku = fs::read_to_string("/sys/devices/virtual/dmi/id/product_sku")
-        .map(|s| s.trim().to_string())
-        .map_err(|e| anyhow::anyhow!("Failed to read product SKU: {}", e))?;
-
-    debug!("Linux product SKU: {}", sku);
+impl CommandStats {
+    pub fn mean_latency(&self) -> time::Duration {
+        self.total_latency / self.calls.max(1)
+    }
 
-    if sku.starts_with("RZ") {
-        Ok(sku.chars().take(10).collect())
-    } else {
-        anyhow::bail!("Invalid SKU format: {}", sku)
+    fn record(&mut self, latency: time::Duration, retries: u32, ok: bool) {
+        self.calls += 1;
+        self.retries += retries;
+        self.failures += !ok as u32;
+        self.total_latency += latency;
+        self.max_latency = self.max_latency.max(latency);
     }
 }
 
-impl Device {
-    const RAZER_VID: u16 = 0x1532;
+/// HID interface of a Razer device, as found by `Device::enumerate`.
+#[derive(Debug, Clone, Serialize)]
+pub struct InterfaceInfo {
+    /// e.g. `/dev/hidraw3`
+    pub path: String,
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub interface_number: i32,
+    pub usage_page: u16,
+    pub usage: u16,
+    pub serial_number: Option<String>,
+    pub manufacturer: Option<String>,
+    pub product: Option<String>,
+    /// Whether the interface accepted the probe feature report.
+    pub feature_reports: bool,
+    pub probe_error: Option<String>,
+    /// Model number prefix of the supported descriptor matching the model and PID.
+    pub descriptor: Option<&'static str>,
+}
 
-    pub fn info(&self) -> &Descriptor {
-        &self.info
+#[derive(Debug, Clone, Serialize)]
+pub struct Enumeration {
+    /// Model number read from the BIOS, e.g. `RZ09-0482X`, if it is a Razer laptop.
+    pub model: Option<String>,
+    /// What the model was identified from, empty if it could not be read.
+    pub system: SystemInfo,
+    pub interfaces: Vec<InterfaceInfo>,
+}
+
+impl Enumeration {
+    /// Distinct PIDs of the interfaces.
+    pub fn pids(&self) -> Vec<u16> {
+        self.interfaces
+            .iter()
+            .map(|interface| interface.product_id)
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect()
     }
+}
 
-    pub fn new(descriptor: Descriptor) -> Result<Device> {
-        let api = hidapi::HidApi::new().context("Failed to create hid api")?;
+/// Which HID interface to open.
+#[derive(Debug, Clone)]
+enum Interface {
+    // there are multiple devices with the same pid, pick first that support feature report
+    FirstOfPid,
+    Path(String),
+    Serial(String),
+}
+
+impl Interface {
+    fn open(&self, descriptor: &Descriptor) -> Result<(hidapi::HidDevice, String)> {
+        let api = hidapi::HidApi::new()?;
 
-        // there are multiple devices with the same pid, pick first that support feature report
-        for info in api.device_list().filter(|info| {
-            (info.vendor_id(), info.product_id()) == (Device::RAZER_VID, descriptor.pid)
+        for info in api.device_list().filter(|info| match self {
+            Interface::FirstOfPid => {
+                (info.vendor_id(), info.product_id()) == (Device::RAZER_VID, descriptor.pid)
+            }
+            Interface::Path(path) => info.path().to_string_lossy() == path.as_str(),
+            Interface::Serial(serial) => {
+                (info.vendor_id(), info.product_id()) == (Device::RAZER_VID, descriptor.pid)
+                    && info.serial_number() == Some(serial.as_str())
+            }
         }) {
             let path = info.path();
             let device = api.open_path(path)?;
             if device.send_feature_report(&[0, 0]).is_ok() {
-                return Ok(Device {
-                    device,
-                    info: descriptor.clone(),
-                });
+                return Ok((device, path.to_string_lossy().into_owned()));
             }
         }
-        anyhow::bail!("Failed to open device {:?}", descriptor)
+        Err(Error::DeviceNotFound(format!(
+            "Failed to open device {:?} ({:?})",
+            descriptor, self
+        )))
+    }
+}
+
+impl Device {
+    pub(crate) const RAZER_VID: u16 = 0x1532;
+
+    pub fn info(&self) -> &Descriptor {
+        &self.info
+    }
+
+    pub fn new(descriptor: Descriptor) -> Result<Device> {
+        Device::open(descriptor, Interface::FirstOfPid)
+    }
+
+    /// Open the interface at `path`, e.g. `/dev/hidraw3`, as listed by `Device::enumerate`.
+    pub fn open_path(descriptor: Descriptor, path: &str) -> Result<Device> {
+        Device::open(descriptor, Interface::Path(path.to_string()))
+    }
+
+    /// Open the first interface of the descriptor PID with the given serial number.
+    pub fn open_serial(descriptor: Descriptor, serial: &str) -> Result<Device> {
+        Device::open(descriptor, Interface::Serial(serial.to_string()))
+    }
+
+    fn open(descriptor: Descriptor, interface: Interface) -> Result<Device> {
+        let (device, path) = interface.open(&descriptor)?;
+        let mut device = Device::with_transport(descriptor, device).with_lock(&path);
+        device.interface = Some(interface);
+        device.init()?;
+        Ok(device)
+    }
+
+    /// Open the HID interface of the descriptor again, e.g. after resume or an EC reset, keeping
+    /// the policy and the stats, and run the init sequence. Pairs with `monitor::Monitor` to know
+    /// when it is back.
+    pub fn reconnect(&mut self) -> Result<()> {
+        let Some(interface) = &self.interface else {
+            return Err(Error::InvalidState(
+                "Only devices opened from a HID interface can reconnect".to_string(),
+            ));
+        };
+        let (device, path) = interface.open(&self.info)?;
+        self.transport = Box::new(device);
+        self.lock = Some(DeviceLock::new(&path));
+        self.init()
+    }
+
+    /// Send the init steps of the descriptor in order, stopping at the first one that fails or
+    /// gets an unexpected response. `new`, `open_path`, `open_serial` and `reconnect` run it,
+    /// devices built `with_transport` have to run it themselves.
+    pub fn init(&self) -> Result<()> {
+        let _lock = self.lock()?;
+        for (step, init) in self.info.init.iter().enumerate() {
+            let result = command::ensure_fits(init.args).and_then(|()| {
+                let report = Packet::new(init.command, init.args);
+                let response = self.send(report.clone())?;
+                match init.expect {
+                    Some(expect) if !response.get_args().starts_with(expect) => {
+                        Err(Error::ResponseMismatch {
+                            command: init.command,
+                            reason: "unexpected init response",
+                            report: (&report).into(),
+                            response: (&response).into(),
+                        })
+                    }
+                    _ => Ok(()),
+                }
+            });
+            result.map_err(|e| Error::InitStep {
+                step,
+                command: init.command,
+                source: Box::new(e),
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Build a device on top of an arbitrary transport, e.g. an in-memory fake in tests.
+    pub fn with_transport(descriptor: Descriptor, transport: impl Transport + 'static) -> Device {
+        Device {
+            transport: Box::new(transport),
+            policy: descriptor.policy,
+            stats: RefCell::new(BTreeMap::new()),
+            last_id: Cell::new(0),
+            lock: None,
+            interface: None,
+            info: descriptor,
+        }
+    }
+
+    /// Guard every transaction with the cross-process lock of `key`, `Device::new` uses the
+    /// hid path of the device.
+    pub fn with_lock(mut self, key: &str) -> Device {
+        self.lock = Some(DeviceLock::new(key));
+        self
+    }
+
+    /// Hold the cross-process lock until the guard is dropped, to run several commands without
+    /// another process getting in between. Transactions take it on their own.
+    pub fn lock(&self) -> Result<LockGuard<'_>> {
+        match &self.lock {
+            Some(lock) => lock.acquire(self.policy.lock_timeout),
+            None => Ok(LockGuard::none()),
+        }
+    }
+
+    /// Replace the timings taken from the descriptor.
+    pub fn with_policy(mut self, policy: DevicePolicy) -> Device {
+        self.policy = policy;
+        self
+    }
+
+    pub fn policy(&self) -> &DevicePolicy {
+        &self.policy
+    }
+
+    /// Latency and retry counters of every command sent so far.
+    pub fn stats(&self) -> BTreeMap<u16, CommandStats> {
+        self.stats.borrow().clone()
+    }
+
+    /// Send the read-only getter of every feature and classify the features by the answers,
+    /// e.g. to find out what an unknown model supports. Nothing is written to the device.
+    pub fn probe_capabilities(&self) -> Capabilities {
+        probe::probe(self)
+    }
+
+    /// Replace the transport with a wrapper around it, e.g. to record the session.
+    pub fn map_transport<T: Transport + 'static>(
+        self,
+        wrap: impl FnOnce(Box<dyn Transport>) -> T,
+    ) -> Device {
+        let mut device = Device::with_transport(self.info, wrap(self.transport));
+        device.policy = self.policy;
+        device.stats = self.stats;
+        device.last_id = self.last_id;
+        device.lock = self.lock;
+        device.interface = self.interface;
+        device
     }
 
     pub fn send(&self, report: Packet) -> Result<Packet> {
-        // extra byte for report id
-        let mut response_buf: Vec<u8> = vec![0x00; 1 + std::mem::size_of::<Packet>()];
-        //println!("Report {:?}", report);
+        self.transact(report, false)
+    }
 
-        const MAX_RETRIES: usize = 5;
+    /// Send `payload` split across as many reports as it takes, see `Packet::MAX_ARGS`, and
+    /// return the data of every response report. The response to the last report may announce
+    /// further response reports with `remaining_packets`, they are read and appended as well.
+    pub fn send_payload(&self, command: u16, payload: &[u8]) -> Result<Vec<u8>> {
+        let _lock = self.lock()?;
+        let reports = Packet::chunks(command, payload)?;
+        let last = reports.len() - 1;
 
-        for attempt in 0..MAX_RETRIES {
-            thread::sleep(time::Duration::from_micros(1000));
+        let mut data = Vec::with_capacity(payload.len());
+        let mut response = None;
+        for (i, report) in reports.into_iter().enumerate() {
+            let r = self.transact(report, i == last)?;
+            data.extend_from_slice(r.data());
+            response = Some(r);
+        }
 
-            self.device
-                .send_feature_report(
-                    [0_u8; 1] // report id
-                        .iter()
-                        .copied()
-                        .chain(Into::<Vec<u8>>::into(&report).into_iter())
-                        .collect::<Vec<_>>()
-                        .as_slice(),
-                )
-                .context("Failed to send feature report")?;
+        let mut previous = response.expect("at least one report");
+        for remaining in (0..previous.remaining_packets()).rev() {
+            thread::sleep(self.policy.read_delay);
+            let response = self.read_response()?;
+            response.ensure_continues(&previous, remaining)?;
+            data.extend_from_slice(response.data());
+            previous = response;
+        }
+        Ok(data)
+    }
 
-            thread::sleep(time::Duration::from_micros(2000));
+    // `continued`: the response may announce more response reports
+    fn transact(&self, report: Packet, continued: bool) -> Result<Packet> {
+        let start = time::Instant::now();
+        let report = self.prepare(report);
+        debug!("report {}", report);
 
-            let response_size = self.device.get_feature_report(&mut response_buf)?;
-            if response_buf.len() != response_size {
-                return Err(anyhow!("Response size != {}", response_buf.len()));
+        // Busy, Timeout and stale responses are retried, Failure and NotSupported are final
+        let retry = &self.policy.retry;
+        let mut retries = 0;
+        let result = loop {
+            match self.attempt(&report, continued) {
+                Err(e) if e.is_transient() && retries + 1 < retry.max_attempts => {
+                    debug!("retrying after {}", e);
+                    thread::sleep(retry.delay(retries));
+                    retries += 1;
+                }
+                result => break result,
             }
+        };
+
+        self.record(
+            report.command(),
+            start.elapsed(),
+            retries as u32,
+            result.is_ok(),
+        );
+        result
+    }
 
-            // skip report id byte
-            let response = <&[u8] as TryInto<Packet>>::try_into(&response_buf[1..])?;
-            //println!("Response {:?}", response);
+    /// Stamp the transaction id and protocol type of the descriptor on `report`, retries of it
+    /// keep the id.
+    pub(crate) fn prepare(&self, report: Packet) -> Packet {
+        let protocol = self.info.protocol;
+        let id = match protocol.transaction_id {
+            TransactionId::Random => rand::random(),
+            TransactionId::Fixed(id) => id,
+            TransactionId::Incrementing => self.last_id.get().wrapping_add(1),
+        };
+        self.last_id.set(id);
+        report.with_header(id, protocol.protocol_type)
+    }
 
-            if response.ensure_matches_report(&report).is_ok() {
-                return Ok(response);
-            } else if attempt == MAX_RETRIES - 1 {
-                return Err(anyhow!("Failed to match report after {} attempts", MAX_RETRIES));
-            }
+    /// Single write/read pair, without retries.
+    pub(crate) fn attempt(&self, report: &Packet, continued: bool) -> Result<Packet> {
+        let _lock = self.lock()?;
 
-            // Add a small delay before retrying
-            thread::sleep(time::Duration::from_millis(500));
+        thread::sleep(self.policy.pre_send_delay);
+
+        self.transport.send_feature_report(
+            [0_u8; 1] // report id
+                .iter()
+                .copied()
+                .chain(Into::<Vec<u8>>::into(report))
+                .collect::<Vec<_>>()
+                .as_slice(),
+        )?;
+
+        let command = report.command();
+        thread::sleep(self.policy.read_delay + quirks::extra_delay(self.info.quirks, command));
+
+        let mut response = self.read_response()?;
+        if continued && report.remaining_packets() == 0 && response.remaining_packets() > 0 {
+            let quirks = [
+                self.info.quirks,
+                &[Quirk::ToleratedMismatch {
+                    command,
+                    field: HeaderField::RemainingPackets,
+                }],
+            ]
+            .concat();
+            response.ensure_matches_report(report, &quirks)?;
+        } else {
+            response.ensure_matches_report(report, self.info.quirks)?;
         }
+        match quirks::response_offset(self.info.quirks, command) {
+            0 => (),
+            offset => response.shift_args(offset),
+        }
+        Ok(response)
+    }
 
-        Err(anyhow!("Failed to send feature report"))
+    fn read_response(&self) -> Result<Packet> {
+        // extra byte for report id
+        let mut response_buf: Vec<u8> = vec![0x00; 1 + std::mem::size_of::<Packet>()];
+        let response_size = self.transport.get_feature_report(&mut response_buf)?;
+        if response_buf.len() != response_size {
+            return Err(Error::InvalidReport {
+                reason: format!("Response size != {}", response_buf.len()),
+                data: response_buf[..response_size.min(response_buf.len())].to_vec(),
+            });
+        }
+
+        // skip report id byte
+        let response = <&[u8] as TryInto<Packet>>::try_into(&response_buf[1..])?;
+        debug!("response {}", response);
+        Ok(response)
     }
 
-    pub fn enumerate() -> Result<(Vec<u16>, String)> {
-        let razer_pid_list: Vec<_> = hidapi::HidApi::new()?
+    pub(crate) fn record(&self, command: u16, latency: time::Duration, retries: u32, ok: bool) {
+        self.stats
+            .borrow_mut()
+            .entry(command)
+            .or_default()
+            .record(latency, retries, ok);
+    }
+
+    /// Every Razer HID interface, probed for feature report support.
+    ///
+    /// The probe is written to every interface, including external keyboards, mice and docks,
+    /// so this is for diagnostics only, e.g. `razer-cli enumerate`. `Device::detect` only looks
+    /// at the PIDs of the interfaces, see `Device::razer_pids`.
+    pub fn enumerate() -> Result<Enumeration> {
+        let api = hidapi::HidApi::new()?;
+        let system = SystemInfo::read()
+            .inspect_err(|e| debug!("{}", e))
+            .unwrap_or_default();
+        let model = system.model_number();
+
+        let interfaces: Vec<_> = api
             .device_list()
             .filter(|info| info.vendor_id() == Device::RAZER_VID)
-            .map(|info| info.product_id())
-            .collect::<std::collections::HashSet<_>>()
-            .into_iter()
+            .map(|info| {
+                // the same probe `Device::new` picks the interface with
+                let probe = api
+                    .open_path(info.path())
+                    .and_then(|device| device.send_feature_report(&[0, 0]));
+                InterfaceInfo {
+                    path: info.path().to_string_lossy().into_owned(),
+                    vendor_id: info.vendor_id(),
+                    product_id: info.product_id(),
+                    interface_number: info.interface_number(),
+                    usage_page: info.usage_page(),
+                    usage: info.usage(),
+                    serial_number: info.serial_number().map(str::to_string),
+                    manufacturer: info.manufacturer_string().map(str::to_string),
+                    product: info.product_string().map(str::to_string),
+                    feature_reports: probe.is_ok(),
+                    probe_error: probe.err().map(|e| e.to_string()),
+                    descriptor: matching::find(
+                        &system,
+                        &[info.product_id()],
+                        descriptor::supported(),
+                    )
+                    .filter(|found| found.descriptor.pid == info.product_id())
+                    .map(|found| found.descriptor.model_number_prefix),
+                }
+            })
             .collect();
 
-        if razer_pid_list.is_empty() {
-            anyhow::bail!("No Razer devices found")
+        if interfaces.is_empty() {
+            return Err(Error::DeviceNotFound("No Razer devices found".to_string()));
         }
+        Ok(Enumeration {
+            model,
+            system,
+            interfaces,
+        })
+    }
 
-        match read_device_model() {
-            Ok(model) if model.starts_with("RZ09-") => Ok((razer_pid_list, model)),
-            Ok(model) => anyhow::bail!("Detected model but it's not a Razer laptop: {}", model),
-            Err(e) => anyhow::bail!("Failed to detect model: {}", e),
+    /// Distinct PIDs of the Razer HID interfaces, nothing is opened.
+    pub fn razer_pids() -> Result<Vec<u16>> {
+        let api = hidapi::HidApi::new()?;
+        let pids: BTreeSet<_> = api
+            .device_list()
+            .filter(|info| info.vendor_id() == Device::RAZER_VID)
+            .map(|info| info.product_id())
+            .collect();
+        if pids.is_empty() {
+            return Err(Error::DeviceNotFound("No Razer devices found".to_string()));
         }
+        Ok(pids.into_iter().collect())
+    }
+
+    /// PID of the Razer HID interface at `path`, nothing is opened.
+    pub fn razer_pid_of_path(path: &str) -> Result<u16> {
+        let api = hidapi::HidApi::new()?;
+        let pid = api
+            .device_list()
+            .filter(|info| info.vendor_id() == Device::RAZER_VID)
+            .find(|info| info.path().to_str() == Ok(path))
+            .map(|info| info.product_id());
+        pid.ok_or_else(|| Error::DeviceNotFound(format!("No Razer HID interface at {}", path)))
     }
 
     pub fn detect() -> Result<Device> {
-        let (pid_list, model_number_prefix) = Device::enumerate()?;
+        Device::new(Device::detect_descriptor()?)
+    }
 
-        match SUPPORTED
-            .iter()
-            .find(|supported| model_number_prefix == supported.model_number_prefix)
-        {
-            Some(supported) => Device::new(supported.clone()),
-            None => anyhow::bail!(
-                "Model {} with PIDs {:0>4x?} is not supported",
-                model_number_prefix,
-                pid_list
-            ),
+    /// Descriptor of the supported model this laptop is, e.g. to open it with `open_path`.
+    pub fn detect_descriptor() -> Result<Descriptor> {
+        Device::detect_descriptor_from(&SystemInfo::read()?)
+    }
+
+    /// Same as `detect_descriptor` with the given model identification, e.g. read from a
+    /// fixture sysfs tree with `SystemInfo::from_sysfs`.
+    pub fn detect_descriptor_from(system: &SystemInfo) -> Result<Descriptor> {
+        let pids = Device::razer_pids()?;
+
+        match matching::find(system, &pids, descriptor::supported()) {
+            Some(found) => {
+                debug!("{} matched by {:?}", found.descriptor.name, found.kind);
+                Ok(found.descriptor)
+            }
+            None => Err(Error::UnsupportedModel {
+                model: system
+                    .sku
+                    .clone()
+                    .or_else(|| system.product_name.clone())
+                    .unwrap_or_else(|| "Unknown".to_string()),
+                pids,
+            }),
         }
     }
 }
@@ -0,0 +1,106 @@
+use thiserror::Error;
+
+/// Errors returned by librazer.
+#[derive(Error, Debug)]
+pub enum Error {
+    /// The device answered with the NotSupported status.
+    #[error("Command {command:#06x} not supported")]
+    NotSupported { command: u16 },
+
+    /// The device answered with the Failure status or an unknown one.
+    #[error("Command {command:#06x} failed with status {status:#04x}")]
+    CommandFailed { command: u16, status: u8 },
+
+    /// The device was still busy after the last retry.
+    #[error("Command {command:#06x} failed, device is busy")]
+    Busy { command: u16 },
+
+    /// The device timed out processing the command after the last retry.
+    #[error("Command {command:#06x} timed out")]
+    Timeout { command: u16 },
+
+    /// The response does not belong to the report or does not echo its arguments.
+    #[error("Response to command {command:#06x} does not match the report: {reason}")]
+    ResponseMismatch {
+        command: u16,
+        reason: &'static str,
+        report: Vec<u8>,
+        response: Vec<u8>,
+    },
+
+    /// Raw bytes that can't be parsed as a report.
+    #[error("Invalid report: {reason}")]
+    InvalidReport { reason: String, data: Vec<u8> },
+
+    /// A byte that does not map to a variant of the given type.
+    #[error("Failed to convert {value:#04x} to {kind}")]
+    InvalidValue { kind: &'static str, value: u8 },
+
+    #[error("Invalid argument: {0}")]
+    InvalidArgument(String),
+
+    /// The device is in a state that does not allow the operation.
+    #[error("{0}")]
+    InvalidState(String),
+
+    /// Failure of the underlying transport, e.g. hidapi.
+    #[error("Transport failure: {0}")]
+    Transport(#[source] Box<dyn std::error::Error + Send + Sync>),
+
+    /// Another process held the device lock for longer than the timeout.
+    #[error("Device is locked by another process ({}), gave up after {timeout:?}", path.display())]
+    Locked {
+        path: std::path::PathBuf,
+        timeout: std::time::Duration,
+    },
+
+    /// A step of the init sequence of the descriptor failed, see `Device::init`.
+    #[error("Init step {step} (command {command:#06x}) failed: {source}")]
+    InitStep {
+        step: usize,
+        command: u16,
+        #[source]
+        source: Box<Error>,
+    },
+
+    /// A change of `DeviceState::apply_transaction` failed, the report tells what was rolled back.
+    #[error("Transaction failed: {0}")]
+    Transaction(Box<crate::state::ApplyReport>),
+
+    #[error("Device not found: {0}")]
+    DeviceNotFound(String),
+
+    #[error("Failed to detect model: {0}")]
+    ModelDetection(String),
+
+    #[error("Model {model} with PIDs {pids:04x?} is not supported")]
+    UnsupportedModel { model: String, pids: Vec<u16> },
+
+    #[error("Replay diverged at exchange #{position}: {message}")]
+    ReplayDiverged { position: usize, message: String },
+
+    /// Malformed input file, e.g. a capture or a recording.
+    #[error("Invalid data: {0}")]
+    InvalidData(String),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+impl From<hidapi::HidError> for Error {
+    fn from(error: hidapi::HidError) -> Self {
+        Error::Transport(Box::new(error))
+    }
+}
+
+impl Error {
+    /// Whether sending the same report again might succeed.
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            Error::Busy { .. } | Error::Timeout { .. } | Error::ResponseMismatch { .. }
+        )
+    }
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
@@ -1,7 +1,28 @@
+#[cfg(feature = "async")]
+pub mod async_device;
+pub mod capture;
 pub mod command;
+pub mod commands;
 pub mod device;
 pub mod feature;
+pub mod lock;
+pub mod matching;
+pub mod monitor;
+pub mod policy;
+pub mod probe;
+pub mod protocol;
+pub mod quirks;
+pub mod registry;
+pub mod replay;
+pub mod shared;
+pub mod sim;
+pub mod state;
+pub mod transport;
 pub mod types;
 
 pub mod descriptor;
+mod error;
 mod packet;
+
+pub use error::{Error, Result};
+pub use packet::Packet;
@@ -0,0 +1,99 @@
+use crate::{Error, Result};
+
+use fs2::FileExt;
+use std::cell::{Cell, RefCell};
+use std::fs::{File, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::{thread, time};
+
+/// Advisory lock shared by every process talking to the same device, e.g. razer-cli and
+/// razer-tray, so their write/read pairs never interleave.
+///
+/// The lock file lives in `$XDG_RUNTIME_DIR`, or the temp dir if it is not set. Acquiring is
+/// reentrant within the owning `Device`.
+pub struct DeviceLock {
+    path: PathBuf,
+    file: RefCell<Option<File>>,
+    depth: Cell<usize>,
+}
+
+/// Holds the lock until dropped.
+pub struct LockGuard<'a>(Option<&'a DeviceLock>);
+
+impl DeviceLock {
+    /// Lock for the device identified by `key`, e.g. its hidraw path.
+    pub fn new(key: &str) -> DeviceLock {
+        let name: String = key
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        let dir = std::env::var_os("XDG_RUNTIME_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(std::env::temp_dir);
+        DeviceLock {
+            path: dir.join(format!("librazer-{}.lock", name)),
+            file: RefCell::new(None),
+            depth: Cell::new(0),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // Without `$XDG_RUNTIME_DIR` the file is shared with other users, who can't open the one
+    // created by the first user for writing. A read-only descriptor is enough to lock it.
+    fn open(&self) -> Result<File> {
+        let created = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&self.path);
+        match created {
+            Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => {
+                Ok(File::open(&self.path)?)
+            }
+            result => Ok(result?),
+        }
+    }
+
+    pub(crate) fn acquire(&self, timeout: time::Duration) -> Result<LockGuard<'_>> {
+        if self.depth.get() == 0 {
+            let file = self.open()?;
+            let start = time::Instant::now();
+            while file.try_lock_exclusive().is_err() {
+                if start.elapsed() >= timeout {
+                    return Err(Error::Locked {
+                        path: self.path.clone(),
+                        timeout,
+                    });
+                }
+                thread::sleep(time::Duration::from_millis(5));
+            }
+            *self.file.borrow_mut() = Some(file);
+        }
+        self.depth.set(self.depth.get() + 1);
+        Ok(LockGuard(Some(self)))
+    }
+}
+
+impl LockGuard<'_> {
+    /// Guard of a device without a lock, e.g. a simulated one.
+    pub(crate) fn none() -> LockGuard<'static> {
+        LockGuard(None)
+    }
+}
+
+impl Drop for LockGuard<'_> {
+    fn drop(&mut self) {
+        if let Some(lock) = self.0 {
+            lock.depth.set(lock.depth.get() - 1);
+            if lock.depth.get() == 0 {
+                if let Some(file) = lock.file.borrow_mut().take() {
+                    let _ = FileExt::unlock(&file);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,223 @@
+use crate::descriptor::Descriptor;
+#[cfg(target_os = "windows")]
+use crate::Error;
+use crate::Result;
+
+use serde::Serialize;
+use std::path::Path;
+
+/// Model identification of the laptop, from DMI on Linux and the BIOS registry key on Windows.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct SystemInfo {
+    /// e.g. `RZ09-05289EB3`, empty on some BIOSes.
+    pub sku: Option<String>,
+    /// e.g. `Blade 16 - RZ09-0528`
+    pub product_name: Option<String>,
+    pub board_name: Option<String>,
+}
+
+impl SystemInfo {
+    pub const SYSFS_ROOT: &'static str = "/sys";
+
+    #[cfg(target_os = "linux")]
+    pub fn read() -> Result<SystemInfo> {
+        Ok(SystemInfo::from_sysfs(Path::new(SystemInfo::SYSFS_ROOT)))
+    }
+
+    #[cfg(target_os = "windows")]
+    pub fn read() -> Result<SystemInfo> {
+        let hklm = winreg::RegKey::predef(winreg::enums::HKEY_LOCAL_MACHINE);
+        let bios = hklm
+            .open_subkey("HARDWARE\\DESCRIPTION\\System\\BIOS")
+            .map_err(|e| Error::ModelDetection(format!("Failed to open BIOS key: {}", e)))?;
+        let value = |name| bios.get_value::<String, _>(name).ok().and_then(non_empty);
+        Ok(SystemInfo {
+            sku: value("SystemSKU"),
+            product_name: value("SystemProductName"),
+            board_name: value("BaseBoardProduct"),
+        })
+    }
+
+    /// DMI attributes under `root`, e.g. `/sys` or a fixture tree laid out the same way.
+    /// Missing and empty attributes are `None`.
+    pub fn from_sysfs(root: &Path) -> SystemInfo {
+        let dmi = root.join("devices/virtual/dmi/id");
+        let attribute = |name| {
+            std::fs::read_to_string(dmi.join(name))
+                .inspect_err(|e| log::debug!("Failed to read DMI {}: {}", name, e))
+                .ok()
+                .and_then(non_empty)
+        };
+        SystemInfo {
+            sku: attribute("product_sku"),
+            product_name: attribute("product_name"),
+            board_name: attribute("board_name"),
+        }
+    }
+
+    /// Model number prefix of the SKU, clipped to conform with
+    /// https://mysupport.razer.com/app/answers/detail/a_id/5481, if it is a Razer laptop.
+    pub fn model_number(&self) -> Option<String> {
+        self.sku
+            .as_deref()
+            .filter(|sku| sku.starts_with("RZ09-"))
+            .map(|sku| sku.chars().take(10).collect())
+    }
+}
+
+fn non_empty(value: String) -> Option<String> {
+    Some(value.trim().to_string()).filter(|value| !value.is_empty())
+}
+
+/// What a descriptor was matched by, from the most to the least reliable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub enum MatchKind {
+    /// The SKU starts with the model number prefix.
+    Sku,
+    /// The SKU matches a model number prefix with wildcards, e.g. `RZ09-0528?`.
+    Wildcard,
+    /// The model number or name in the DMI product name.
+    ProductName,
+    /// The model number in the DMI board name.
+    BoardName,
+    /// Only the PID, a best guess.
+    Pid,
+}
+
+#[derive(Debug, Clone)]
+pub struct ModelMatch {
+    pub descriptor: Descriptor,
+    pub kind: MatchKind,
+}
+
+/// Whether `model`, e.g. a SKU, starts with `pattern`, ignoring case.
+///
+/// `?` in the pattern matches any character, `*` any run of characters.
+pub fn matches_model(pattern: &str, model: &str) -> bool {
+    glob(pattern.as_bytes(), model.as_bytes(), false)
+}
+
+// `partial` also accepts text that ends before the pattern does, e.g. a model number without
+// the last character
+fn glob(pattern: &[u8], text: &[u8], partial: bool) -> bool {
+    match (pattern.split_first(), text.split_first()) {
+        (None, _) => true,
+        (Some((b'*', rest)), _) => (0..=text.len()).any(|i| glob(rest, &text[i..], partial)),
+        (Some(_), None) => partial,
+        (Some((b'?', rest)), Some((_, text))) => glob(rest, text, partial),
+        (Some((p, rest)), Some((t, text))) => {
+            p.eq_ignore_ascii_case(t) && glob(rest, text, partial)
+        }
+    }
+}
+
+fn is_wildcard(pattern: &str) -> bool {
+    pattern.contains(['*', '?'])
+}
+
+// model number in a DMI string, e.g. `RZ09-0528` in `Blade 16 - RZ09-0528`
+fn model_number_in(value: &str) -> Option<&str> {
+    let start = value.find("RZ09-")?;
+    let len = value[start..]
+        .find(|c: char| !c.is_ascii_alphanumeric() && c != '-')
+        .unwrap_or(value.len() - start);
+    // anything shorter is shared by too many models
+    Some(&value[start..start + len]).filter(|model| model.len() >= 9)
+}
+
+fn by_dmi_string<'a>(
+    value: &str,
+    mut candidates: impl Iterator<Item = &'a Descriptor>,
+) -> Option<&'a Descriptor> {
+    if let Some(model) = model_number_in(value) {
+        return candidates.find(|descriptor| {
+            glob(
+                descriptor.model_number_prefix.as_bytes(),
+                model.as_bytes(),
+                true,
+            )
+        });
+    }
+    // generic names like `Blade` would match whatever model comes first
+    let name = value.trim_start_matches("Razer ");
+    candidates.find(|descriptor| {
+        descriptor
+            .name
+            .trim_start_matches("Razer ")
+            .eq_ignore_ascii_case(name)
+    })
+}
+
+// descriptor whose name contains a DMI name, to pick the best guess among the PID candidates
+fn named_in<'a>(
+    system: &SystemInfo,
+    mut candidates: impl Iterator<Item = &'a Descriptor>,
+) -> Option<&'a Descriptor> {
+    let name = system
+        .product_name
+        .as_deref()?
+        .trim_start_matches("Razer ")
+        .to_ascii_lowercase();
+    candidates
+        .find(|descriptor| !name.is_empty() && descriptor.name.to_ascii_lowercase().contains(&name))
+}
+
+/// Find the descriptor of the laptop among `descriptors`, trying in order:
+///
+/// 1. the SKU against model number prefixes, exact ones before wildcards,
+/// 2. the model number in the DMI product name, or the exact descriptor name, then the same
+///    in the board name,
+/// 3. the PIDs of the Razer HID interfaces, logging a warning. A descriptor whose name
+///    contains the product name is preferred.
+///
+/// Fallbacks only consider descriptors whose PID is among `pids`, unless it is empty.
+pub fn find(system: &SystemInfo, pids: &[u16], descriptors: &[Descriptor]) -> Option<ModelMatch> {
+    let found = |descriptor: &Descriptor, kind| {
+        Some(ModelMatch {
+            descriptor: descriptor.clone(),
+            kind,
+        })
+    };
+
+    if let Some(sku) = &system.sku {
+        let mut matching: Vec<_> = descriptors
+            .iter()
+            .filter(|descriptor| matches_model(descriptor.model_number_prefix, sku))
+            .collect();
+        matching.sort_by_key(|descriptor| is_wildcard(descriptor.model_number_prefix));
+        if let Some(descriptor) = matching.first() {
+            let kind = match is_wildcard(descriptor.model_number_prefix) {
+                true => MatchKind::Wildcard,
+                false => MatchKind::Sku,
+            };
+            return found(descriptor, kind);
+        }
+    }
+
+    let candidates = descriptors
+        .iter()
+        .filter(|descriptor| pids.is_empty() || pids.contains(&descriptor.pid));
+    for (value, kind) in [
+        (&system.product_name, MatchKind::ProductName),
+        (&system.board_name, MatchKind::BoardName),
+    ] {
+        if let Some(descriptor) = value
+            .as_deref()
+            .and_then(|value| by_dmi_string(value, candidates.clone()))
+        {
+            return found(descriptor, kind);
+        }
+    }
+
+    if pids.is_empty() {
+        return None;
+    }
+    let descriptor = named_in(system, candidates.clone()).or(candidates.clone().next())?;
+    log::warn!(
+        "Model {:?} is not known, best guess by PID {:#06x}: {}",
+        system.sku,
+        descriptor.pid,
+        descriptor.name
+    );
+    found(descriptor, MatchKind::Pid)
+}
@@ -0,0 +1,241 @@
+use crate::device::Device;
+use crate::Result;
+
+use std::collections::BTreeSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::{thread, time};
+
+/// Razer HID interface that appeared or disappeared.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeviceEvent {
+    Added { pid: u16, path: String },
+    Removed { pid: u16, path: String },
+}
+
+/// Watches Razer interfaces (VID 0x1532) coming and going, e.g. on resume or an EC reset.
+///
+/// On Linux the hidraw uevents of the kernel trigger a rescan, elsewhere, or when the netlink
+/// socket can't be opened, the interfaces are polled. Events are received by iterating the
+/// monitor, the watcher thread stops when it is dropped.
+pub struct Monitor {
+    events: mpsc::Receiver<DeviceEvent>,
+    stop: Arc<AtomicBool>,
+}
+
+impl Monitor {
+    pub const DEFAULT_POLL_INTERVAL: time::Duration = time::Duration::from_secs(2);
+
+    pub fn new() -> Result<Monitor> {
+        Monitor::with_poll_interval(Monitor::DEFAULT_POLL_INTERVAL)
+    }
+
+    /// `interval` is the rescan period of the polling fallback, and the upper bound of the
+    /// reaction time with uevents.
+    pub fn with_poll_interval(interval: time::Duration) -> Result<Monitor> {
+        let mut api = hidapi::HidApi::new()?;
+        let mut known = scan(&api);
+        let (sender, events) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+
+        thread::Builder::new()
+            .name("librazer-monitor".to_string())
+            .spawn({
+                let stop = stop.clone();
+                move || {
+                    let mut trigger = Trigger::new(interval);
+                    while !stop.load(Ordering::Relaxed) {
+                        trigger.wait();
+                        if api.refresh_devices().is_err() {
+                            continue;
+                        }
+                        let current = scan(&api);
+                        for event in changes(&known, &current) {
+                            log::debug!("monitor {:?}", event);
+                            if sender.send(event).is_err() {
+                                return;
+                            }
+                        }
+                        known = current;
+                    }
+                }
+            })?;
+
+        Ok(Monitor { events, stop })
+    }
+
+    /// Next event if there is one, without blocking.
+    pub fn try_next(&self) -> Option<DeviceEvent> {
+        self.events.try_recv().ok()
+    }
+}
+
+impl Iterator for Monitor {
+    type Item = DeviceEvent;
+
+    fn next(&mut self) -> Option<DeviceEvent> {
+        self.events.recv().ok()
+    }
+}
+
+impl Drop for Monitor {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Events turning the `known` (PID, path) interfaces into the `current` ones, removals first.
+pub fn changes(
+    known: &BTreeSet<(u16, String)>,
+    current: &BTreeSet<(u16, String)>,
+) -> Vec<DeviceEvent> {
+    let removed = known
+        .difference(current)
+        .map(|(pid, path)| DeviceEvent::Removed {
+            pid: *pid,
+            path: path.clone(),
+        });
+    let added = current
+        .difference(known)
+        .map(|(pid, path)| DeviceEvent::Added {
+            pid: *pid,
+            path: path.clone(),
+        });
+    removed.chain(added).collect()
+}
+
+fn scan(api: &hidapi::HidApi) -> BTreeSet<(u16, String)> {
+    api.device_list()
+        .filter(|info| info.vendor_id() == Device::RAZER_VID)
+        .map(|info| {
+            (
+                info.product_id(),
+                info.path().to_string_lossy().into_owned(),
+            )
+        })
+        .collect()
+}
+
+/// Blocks until the next rescan is due.
+struct Trigger {
+    interval: time::Duration,
+    #[cfg(target_os = "linux")]
+    uevents: Option<uevent::Socket>,
+}
+
+impl Trigger {
+    fn new(interval: time::Duration) -> Trigger {
+        Trigger {
+            interval,
+            #[cfg(target_os = "linux")]
+            uevents: uevent::Socket::open(interval)
+                .inspect_err(|e| log::warn!("uevents unavailable, polling: {}", e))
+                .ok(),
+        }
+    }
+
+    fn wait(&mut self) {
+        #[cfg(target_os = "linux")]
+        if let Some(uevents) = self.uevents.take() {
+            match uevents.wait_hidraw(self.interval) {
+                Ok(()) => {
+                    self.uevents = Some(uevents);
+                    return;
+                }
+                Err(e) => log::warn!("uevents failed, polling: {}", e),
+            }
+        }
+        thread::sleep(self.interval);
+    }
+}
+
+/// Kernel uevents over netlink, the same feed udev listens to.
+#[cfg(target_os = "linux")]
+mod uevent {
+    use std::io;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+    use std::time;
+
+    pub struct Socket(OwnedFd);
+
+    impl Socket {
+        pub fn open(timeout: time::Duration) -> io::Result<Socket> {
+            // SAFETY: plain libc calls, the fd is owned right after creation
+            unsafe {
+                let fd = libc::socket(
+                    libc::AF_NETLINK,
+                    libc::SOCK_DGRAM | libc::SOCK_CLOEXEC,
+                    libc::NETLINK_KOBJECT_UEVENT,
+                );
+                if fd < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                let socket = Socket(OwnedFd::from_raw_fd(fd));
+
+                let mut address: libc::sockaddr_nl = std::mem::zeroed();
+                address.nl_family = libc::AF_NETLINK as u16;
+                // kernel multicast group
+                address.nl_groups = 1;
+                if libc::bind(
+                    fd,
+                    &address as *const _ as *const libc::sockaddr,
+                    std::mem::size_of::<libc::sockaddr_nl>() as u32,
+                ) < 0
+                {
+                    return Err(io::Error::last_os_error());
+                }
+
+                let timeout = libc::timeval {
+                    tv_sec: timeout.as_secs() as libc::time_t,
+                    tv_usec: timeout.subsec_micros() as libc::suseconds_t,
+                };
+                if libc::setsockopt(
+                    fd,
+                    libc::SOL_SOCKET,
+                    libc::SO_RCVTIMEO,
+                    &timeout as *const _ as *const libc::c_void,
+                    std::mem::size_of::<libc::timeval>() as u32,
+                ) < 0
+                {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(socket)
+            }
+        }
+
+        /// Wait for a uevent about a hidraw node, or until `timeout` in case one was missed.
+        pub fn wait_hidraw(&self, timeout: time::Duration) -> io::Result<()> {
+            let deadline = time::Instant::now() + timeout;
+            let mut buf = [0_u8; 4096];
+            while time::Instant::now() < deadline {
+                // SAFETY: buf outlives the call and its length is passed along
+                let len = unsafe {
+                    libc::recv(
+                        self.0.as_raw_fd(),
+                        buf.as_mut_ptr() as *mut libc::c_void,
+                        buf.len(),
+                        0,
+                    )
+                };
+                if len < 0 {
+                    let error = io::Error::last_os_error();
+                    match error.kind() {
+                        io::ErrorKind::Interrupted => continue,
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => return Ok(()),
+                        _ => return Err(error),
+                    }
+                }
+                // e.g. `add@/devices/.../hidraw/hidraw3\0ACTION=add\0SUBSYSTEM=hidraw\0...`
+                if buf[..len as usize]
+                    .split(|&b| b == 0)
+                    .any(|field| field == b"SUBSYSTEM=hidraw")
+                {
+                    // let the kernel finish setting up the interfaces
+                    std::thread::sleep(time::Duration::from_millis(100));
+                    return Ok(());
+                }
+            }
+            Ok(())
+        }
+    }
+}
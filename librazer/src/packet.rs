@@ -1,4 +1,7 @@
-use anyhow::{ensure, Result};
+use crate::quirks::{self, HeaderField, Quirk};
+use crate::registry;
+use crate::{Error, Result};
+
 use rand::Rng;
 use serde::{Deserialize, Serialize};
 use serde_big_array::BigArray;
@@ -6,10 +9,11 @@ use serde_big_array::BigArray;
 /// Packet is the structure of the packet that is sent to the Razer HID device and received back.
 /// Source https://github.com/Razer-Linux/razer-laptop-control-no-dkms/blob/main/razer_control_gui/src/device.rs.
 #[repr(C)]
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Packet {
     status: u8,
     id: u8,
+    // big endian on the wire, bincode writes it as is
     remaining_packets: u16,
     protocol_type: u8,
     data_size: u8,
@@ -21,18 +25,51 @@ pub struct Packet {
     reserved: u8,
 }
 
-enum CommandStatus {
+/// Source https://github.com/openrazer/openrazer/wiki/Reverse-Engineering-USB-Protocol.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum CommandStatus {
     New = 0x00,
+    Busy = 0x01,
     Successful = 0x02,
+    Failure = 0x03,
+    Timeout = 0x04,
     NotSupported = 0x05,
 }
 
+impl TryFrom<u8> for CommandStatus {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self> {
+        match value {
+            0x00 => Ok(CommandStatus::New),
+            0x01 => Ok(CommandStatus::Busy),
+            0x02 => Ok(CommandStatus::Successful),
+            0x03 => Ok(CommandStatus::Failure),
+            0x04 => Ok(CommandStatus::Timeout),
+            0x05 => Ok(CommandStatus::NotSupported),
+            _ => Err(Error::InvalidValue {
+                kind: "CommandStatus",
+                value,
+            }),
+        }
+    }
+}
+
 impl Packet {
+    /// Arguments that fit in a single report, `Device::send_payload` splits longer ones.
+    pub const MAX_ARGS: usize = 80;
+
+    /// Panics if `args` is longer than `MAX_ARGS`.
     pub fn new(command: u16, args: &[u8]) -> Packet {
+        assert!(
+            args.len() <= Packet::MAX_ARGS,
+            "{} bytes of arguments don't fit in a report, use Device::send_payload",
+            args.len()
+        );
         let mut args_buffer = [0x00; 80];
         args_buffer[..args.len()].copy_from_slice(args);
 
-        Packet {
+        let mut packet = Packet {
             status: CommandStatus::New as u8,
             id: rand::thread_rng().gen(),
             remaining_packets: 0x0000,
@@ -43,43 +80,221 @@ impl Packet {
             args: args_buffer,
             crc: 0x00,
             reserved: 0x00,
+        };
+        packet.crc = packet.compute_crc();
+        packet
+    }
+
+    pub(crate) fn with_header(mut self, id: u8, protocol_type: u8) -> Packet {
+        self.id = id;
+        self.protocol_type = protocol_type;
+        self.crc = self.compute_crc();
+        self
+    }
+
+    /// Consecutive reports carrying `payload`, `remaining_packets` counting down to 0 and
+    /// `data_size` set to the bytes of each. An empty payload takes a single report.
+    pub(crate) fn chunks(command: u16, payload: &[u8]) -> Result<Vec<Packet>> {
+        let chunks: Vec<_> = match payload.is_empty() {
+            true => vec![payload],
+            false => payload.chunks(Packet::MAX_ARGS).collect(),
+        };
+        if chunks.len() > u16::MAX as usize + 1 {
+            return Err(Error::InvalidArgument(format!(
+                "Payload of {} bytes needs more than {} reports",
+                payload.len(),
+                u16::MAX as usize + 1
+            )));
         }
+
+        let count = chunks.len();
+        Ok(chunks
+            .into_iter()
+            .enumerate()
+            .map(|(i, chunk)| {
+                let mut packet = Packet::new(command, chunk);
+                packet.remaining_packets = ((count - 1 - i) as u16).to_be();
+                packet.crc = packet.compute_crc();
+                packet
+            })
+            .collect())
     }
 
+    /// Panics if `args` is longer than `MAX_ARGS`.
     pub fn set_args(&mut self, args: &[u8]) {
-        self.args[..args.len()].copy_from_slice(args)
+        assert!(
+            args.len() <= Packet::MAX_ARGS,
+            "{} bytes of arguments don't fit in a report",
+            args.len()
+        );
+        self.args[..args.len()].copy_from_slice(args);
+        self.crc = self.compute_crc();
+    }
+
+    /// XOR over bytes 2..88 of the report, i.e. everything except status, id, crc and reserved.
+    /// Source https://github.com/openrazer/openrazer/wiki/Reverse-Engineering-USB-Protocol.
+    fn compute_crc(&self) -> u8 {
+        self.remaining_packets
+            .to_le_bytes()
+            .into_iter()
+            .chain([
+                self.protocol_type,
+                self.data_size,
+                self.command_class,
+                self.command_id,
+            ])
+            .chain(self.args)
+            .fold(0, |crc, byte| crc ^ byte)
     }
 
     pub fn get_args(&self) -> &[u8] {
         &self.args
     }
 
-    pub fn ensure_matches_report(&self, report: &Packet) -> Result<()> {
-        ensure!(
-            (report.command_class, report.command_id, report.id)
-                == (self.command_class, self.command_id, self.id),
-            "Response does not match the report"
-        );
+    pub fn status(&self) -> u8 {
+        self.status
+    }
 
-        ensure!(
-            self.remaining_packets == report.remaining_packets
-            || (self.command_class, self.command_id) == (0x07, 0x92) /* 0x0792 (bho) has special handling */
-            || (self.command_class, self.command_id) == (0x07, 0x8f), /* 0x078f max fan speed mode has special handling */
-            "Response command does not match the report"
-        );
+    pub fn transaction_id(&self) -> u8 {
+        self.id
+    }
 
-        ensure!(
-            self.status != CommandStatus::NotSupported as u8,
-            "Command not supported"
-        );
+    pub fn protocol_type(&self) -> u8 {
+        self.protocol_type
+    }
 
-        ensure!(
-            self.status == CommandStatus::Successful as u8,
-            "Command failed with unknown status: {:02X?}",
-            self.status
-        );
+    pub fn data_size(&self) -> usize {
+        self.data_size as usize
+    }
+
+    /// The first `data_size` arguments.
+    pub fn data(&self) -> &[u8] {
+        &self.args[..self.data_size().min(Packet::MAX_ARGS)]
+    }
+
+    /// Reports that follow this one in the same transfer.
+    pub fn remaining_packets(&self) -> u16 {
+        u16::from_be(self.remaining_packets)
+    }
+
+    pub fn command(&self) -> u16 {
+        ((self.command_class as u16) << 8) | self.command_id as u16
+    }
 
-        Ok(())
+    /// Human readable form, e.g. `SET perf mode zone=2 mode=Custom fan=Manual`.
+    pub fn describe(&self) -> String {
+        let args = &self.args[..self.data_size().min(self.args.len())];
+        let is_request = self.status == CommandStatus::New as u8;
+        let text = registry::describe(self.command(), args, is_request);
+        match CommandStatus::try_from(self.status) {
+            _ if is_request => text,
+            Ok(status) => format!("{} ({:?})", text, status),
+            Err(_) => format!("{} (status {:#04x})", text, self.status),
+        }
+    }
+
+    /// Decode a report without checking its crc, e.g. one captured on the wire.
+    pub(crate) fn decode_unchecked(data: &[u8]) -> Result<Packet> {
+        let invalid = |reason: String| Error::InvalidReport {
+            reason,
+            data: data.to_vec(),
+        };
+
+        if data.len() != std::mem::size_of::<Packet>() {
+            return Err(invalid(format!("Invalid raw data size {}", data.len())));
+        }
+        bincode::deserialize::<Packet>(data).map_err(|e| invalid(e.to_string()))
+    }
+
+    pub(crate) fn crc_matches(&self) -> bool {
+        self.crc == self.compute_crc()
+    }
+
+    /// Build the response the device would send back for this report, `data_size` is the length
+    /// of `args`.
+    pub(crate) fn response(&self, status: CommandStatus, args: &[u8]) -> Packet {
+        let mut response = self.clone();
+        response.status = status as u8;
+        response.args = [0x00; 80];
+        response.data_size = args.len() as u8;
+        response.set_args(args);
+        response
+    }
+
+    /// Drop the first `offset` arguments, for a device that answers them late.
+    pub(crate) fn shift_args(&mut self, offset: usize) {
+        let mut args = [0x00; 80];
+        args[..80 - offset.min(80)].copy_from_slice(&self.args[offset.min(80)..]);
+        self.args = args;
+        self.crc = self.compute_crc();
+    }
+
+    /// Check the response belongs to `report` and carries a successful status, tolerating the
+    /// `quirks` of the model.
+    pub fn ensure_matches_report(&self, report: &Packet, quirks: &[Quirk]) -> Result<()> {
+        let command = report.command();
+        let mismatch = |reason| Error::ResponseMismatch {
+            command,
+            reason,
+            report: report.into(),
+            response: self.into(),
+        };
+
+        let class = quirks::response_class(quirks, command).unwrap_or(report.command_class);
+        if (class, report.command_id) != (self.command_class, self.command_id) {
+            return Err(mismatch("command differs"));
+        }
+        if self.id != report.id && !quirks::tolerates(quirks, command, HeaderField::TransactionId) {
+            return Err(mismatch("transaction id differs"));
+        }
+        if self.remaining_packets != report.remaining_packets
+            && !quirks::tolerates(quirks, command, HeaderField::RemainingPackets)
+        {
+            return Err(mismatch("remaining packets differ"));
+        }
+        self.ensure_successful(command)
+    }
+
+    /// Check the response is the next of the response reports announced by `previous`, i.e.
+    /// with `remaining` reports after it.
+    pub(crate) fn ensure_continues(&self, previous: &Packet, remaining: u16) -> Result<()> {
+        if (self.command(), self.id) != (previous.command(), previous.id) {
+            return Err(Error::ResponseMismatch {
+                command: previous.command(),
+                reason: "continuation of another response",
+                report: previous.into(),
+                response: self.into(),
+            });
+        }
+        if self.remaining_packets() != remaining {
+            return Err(Error::ResponseMismatch {
+                command: previous.command(),
+                reason: "response reports out of order",
+                report: previous.into(),
+                response: self.into(),
+            });
+        }
+        self.ensure_successful(previous.command())
+    }
+
+    // errors name `command`, the response may carry another command class
+    fn ensure_successful(&self, command: u16) -> Result<()> {
+        match CommandStatus::try_from(self.status) {
+            Ok(CommandStatus::Successful) => Ok(()),
+            Ok(CommandStatus::Busy) => Err(Error::Busy { command }),
+            Ok(CommandStatus::Timeout) => Err(Error::Timeout { command }),
+            Ok(CommandStatus::NotSupported) => Err(Error::NotSupported { command }),
+            _ => Err(Error::CommandFailed {
+                command,
+                status: self.status,
+            }),
+        }
+    }
+}
+
+impl std::fmt::Display for Packet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.describe())
     }
 }
 
@@ -90,14 +305,20 @@ impl From<&Packet> for Vec<u8> {
 }
 
 impl TryFrom<&[u8]> for Packet {
-    type Error = anyhow::Error;
+    type Error = Error;
 
-    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
-        ensure!(
-            data.len() == std::mem::size_of::<Packet>(),
-            "Invalid raw data size"
-        );
-
-        Ok(bincode::deserialize::<Packet>(data)?)
+    fn try_from(data: &[u8]) -> Result<Self> {
+        let packet = Packet::decode_unchecked(data)?;
+        if !packet.crc_matches() {
+            return Err(Error::InvalidReport {
+                reason: format!(
+                    "CRC mismatch: report carries {:#04x}, computed {:#04x}",
+                    packet.crc,
+                    packet.compute_crc()
+                ),
+                data: data.to_vec(),
+            });
+        }
+        Ok(packet)
     }
 }
@@ -0,0 +1,141 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// How `Device::send` retries a report the device answered with Busy or Timeout, or with a
+/// response to another report.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    /// Total number of attempts, including the first one.
+    pub max_attempts: usize,
+    /// Delay before the first retry.
+    pub initial_delay: Duration,
+    /// Factor the delay grows by after every retry.
+    pub multiplier: u32,
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    /// A fixed 500 ms between attempts, what every supported model has been tested with.
+    pub const DEFAULT: RetryPolicy = RetryPolicy {
+        max_attempts: 5,
+        initial_delay: Duration::from_millis(500),
+        multiplier: 1,
+        max_delay: Duration::from_millis(500),
+    };
+
+    /// Delay before the given retry, starting with 0.
+    pub fn delay(&self, retry: usize) -> Duration {
+        let factor = self
+            .multiplier
+            .saturating_pow(retry.min(u32::MAX as usize) as u32);
+        self.initial_delay
+            .saturating_mul(factor)
+            .min(self.max_delay)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy::DEFAULT
+    }
+}
+
+/// Timings of a single transaction with the EC.
+///
+/// Every `Descriptor` carries its defaults, `DevicePolicy::DEFAULT` unless the model is known to
+/// need something else.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DevicePolicy {
+    /// Sleep before the report is sent.
+    pub pre_send_delay: Duration,
+    /// Sleep between sending the report and reading the response.
+    pub read_delay: Duration,
+    pub retry: RetryPolicy,
+    /// How long to wait for another process to release the device lock.
+    pub lock_timeout: Duration,
+}
+
+impl DevicePolicy {
+    pub const DEFAULT: DevicePolicy = DevicePolicy {
+        pre_send_delay: Duration::from_micros(1000),
+        read_delay: Duration::from_micros(2000),
+        retry: RetryPolicy::DEFAULT,
+        lock_timeout: Duration::from_secs(2),
+    };
+
+    pub const fn pre_send_delay(mut self, delay: Duration) -> Self {
+        self.pre_send_delay = delay;
+        self
+    }
+
+    pub const fn read_delay(mut self, delay: Duration) -> Self {
+        self.read_delay = delay;
+        self
+    }
+
+    pub const fn lock_timeout(mut self, timeout: Duration) -> Self {
+        self.lock_timeout = timeout;
+        self
+    }
+
+    pub const fn max_attempts(mut self, max_attempts: usize) -> Self {
+        self.retry.max_attempts = max_attempts;
+        self
+    }
+
+    pub const fn backoff(
+        mut self,
+        initial_delay: Duration,
+        multiplier: u32,
+        max_delay: Duration,
+    ) -> Self {
+        self.retry.initial_delay = initial_delay;
+        self.retry.multiplier = multiplier;
+        self.retry.max_delay = max_delay;
+        self
+    }
+}
+
+impl Default for DevicePolicy {
+    fn default() -> Self {
+        DevicePolicy::DEFAULT
+    }
+}
+
+/// User overrides of a `DevicePolicy`, e.g. from command line flags or a config file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PolicyOverrides {
+    pub pre_send_delay_us: Option<u64>,
+    pub read_delay_us: Option<u64>,
+    pub max_attempts: Option<usize>,
+    /// Delay before the first retry, later retries back off from it.
+    pub retry_delay_ms: Option<u64>,
+    pub lock_timeout_ms: Option<u64>,
+}
+
+impl PolicyOverrides {
+    pub fn apply(&self, mut policy: DevicePolicy) -> DevicePolicy {
+        if let Some(us) = self.pre_send_delay_us {
+            policy = policy.pre_send_delay(Duration::from_micros(us));
+        }
+        if let Some(us) = self.read_delay_us {
+            policy = policy.read_delay(Duration::from_micros(us));
+        }
+        if let Some(max_attempts) = self.max_attempts {
+            policy = policy.max_attempts(max_attempts);
+        }
+        if let Some(ms) = self.retry_delay_ms {
+            let initial_delay = Duration::from_millis(ms);
+            policy = policy.backoff(
+                initial_delay,
+                policy.retry.multiplier,
+                policy.retry.max_delay.max(initial_delay),
+            );
+        }
+        if let Some(ms) = self.lock_timeout_ms {
+            policy = policy.lock_timeout(Duration::from_millis(ms));
+        }
+        policy
+    }
+}
@@ -0,0 +1,141 @@
+use crate::commands::{
+    GetBatteryCare, GetBoost, GetFanActualRpm, GetFanRpm, GetKeyboardBrightness, GetLightsAlwaysOn,
+    GetLogoPower, GetMaxFanSpeedMode, GetPerfMode, RazerCommand,
+};
+use crate::descriptor::Descriptor;
+use crate::device::Device;
+use crate::feature;
+use crate::packet::Packet;
+use crate::types::{Cluster, FanZone};
+use crate::{Error, Result};
+
+use serde::Serialize;
+
+// command and arguments of a getter
+type Getter = (u16, Result<Vec<u8>>);
+
+fn getter<C: RazerCommand>(request: C::Request) -> Getter {
+    (C::COMMAND, C::encode(&request))
+}
+
+// read-only getters of every feature, the same `command.rs` sends
+fn getters() -> [(&'static str, Vec<Getter>); 6] {
+    [
+        ("battery-care", vec![getter::<GetBatteryCare>(())]),
+        ("lid-logo", vec![getter::<GetLogoPower>(())]),
+        ("lights-always-on", vec![getter::<GetLightsAlwaysOn>(())]),
+        ("kbd-backlight", vec![getter::<GetKeyboardBrightness>(())]),
+        (
+            "fan",
+            vec![
+                getter::<GetFanRpm>(FanZone::Zone1),
+                getter::<GetFanActualRpm>(FanZone::Zone1),
+                getter::<GetMaxFanSpeedMode>(()),
+            ],
+        ),
+        (
+            "perf",
+            vec![
+                getter::<GetPerfMode>(FanZone::Zone1),
+                getter::<GetBoost>(Cluster::Cpu),
+            ],
+        ),
+    ]
+}
+
+/// Outcome of probing a command or a feature.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum Support {
+    Supported,
+    /// The device answered with the NotSupported status.
+    NotSupported,
+    /// Any other error, e.g. a timeout or a failure status.
+    Failed(String),
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CommandProbe {
+    pub command: u16,
+    pub support: Support,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FeatureProbe {
+    pub feature: &'static str,
+    /// Supported if every getter is, NotSupported if any of them is not.
+    pub support: Support,
+    pub commands: Vec<CommandProbe>,
+}
+
+/// Features of a device as found by `Device::probe_capabilities`.
+#[derive(Debug, Clone, Serialize)]
+pub struct Capabilities {
+    pub features: Vec<FeatureProbe>,
+}
+
+impl Capabilities {
+    pub fn supported(&self) -> Vec<&'static str> {
+        self.features
+            .iter()
+            .filter(|probe| probe.support == Support::Supported)
+            .map(|probe| probe.feature)
+            .collect()
+    }
+
+    /// `base` with only the supported features enabled, e.g. the descriptor of an unknown model.
+    pub fn descriptor(&self, base: &Descriptor) -> Descriptor {
+        let supported = self.supported();
+        let features: &'static [&'static str] = Box::leak(
+            feature::ALL_FEATURES
+                .iter()
+                .filter(|feature| supported.contains(feature))
+                .copied()
+                .collect::<Vec<_>>()
+                .into_boxed_slice(),
+        );
+        Descriptor {
+            features,
+            ..base.clone()
+        }
+    }
+}
+
+pub(crate) fn probe(device: &Device) -> Capabilities {
+    let features = getters()
+        .into_iter()
+        .map(|(feature, getters)| {
+            let commands: Vec<_> = getters
+                .into_iter()
+                .map(|(command, args)| CommandProbe {
+                    command,
+                    support: match args.and_then(|args| device.send(Packet::new(command, &args))) {
+                        Ok(_) => Support::Supported,
+                        Err(Error::NotSupported { .. }) => Support::NotSupported,
+                        Err(e) => Support::Failed(e.to_string()),
+                    },
+                })
+                .collect();
+
+            let support = if commands.iter().all(|c| c.support == Support::Supported) {
+                Support::Supported
+            } else if commands.iter().any(|c| c.support == Support::NotSupported) {
+                Support::NotSupported
+            } else {
+                commands
+                    .iter()
+                    .map(|c| c.support.clone())
+                    .find(|support| matches!(support, Support::Failed(_)))
+                    .unwrap_or(Support::NotSupported)
+            };
+            log::debug!("probed {}: {:?}", feature, support);
+
+            FeatureProbe {
+                feature,
+                support,
+                commands,
+            }
+        })
+        .collect();
+
+    Capabilities { features }
+}
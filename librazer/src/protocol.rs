@@ -0,0 +1,46 @@
+use serde::Deserialize;
+
+/// How the transaction id of every report is picked. Responses carry the id of their report.
+///
+/// Source https://github.com/openrazer/openrazer/wiki/Reverse-Engineering-USB-Protocol.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TransactionId {
+    /// A new random id for every report, what the supported laptops take.
+    Random,
+    /// The same id for every report, e.g. 0x1f, 0x3f or 0xff on older devices and accessories.
+    Fixed(u8),
+    /// One more than the previous report, wrapping around.
+    Incrementing,
+}
+
+/// Header fields of the reports a device expects.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct Protocol {
+    pub transaction_id: TransactionId,
+    pub protocol_type: u8,
+}
+
+impl Protocol {
+    pub const DEFAULT: Protocol = Protocol {
+        transaction_id: TransactionId::Random,
+        protocol_type: 0x00,
+    };
+
+    pub const fn transaction_id(mut self, transaction_id: TransactionId) -> Self {
+        self.transaction_id = transaction_id;
+        self
+    }
+
+    pub const fn protocol_type(mut self, protocol_type: u8) -> Self {
+        self.protocol_type = protocol_type;
+        self
+    }
+}
+
+impl Default for Protocol {
+    fn default() -> Self {
+        Protocol::DEFAULT
+    }
+}
@@ -0,0 +1,69 @@
+use serde::Deserialize;
+use std::time::Duration;
+
+/// Header field of a response that is compared against the report.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum HeaderField {
+    TransactionId,
+    RemainingPackets,
+}
+
+/// Way the EC of a model deviates from the protocol for a command, consulted by
+/// `Packet::ensure_matches_report` and `Device::send`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Quirk {
+    /// The response may differ from the report in the given header field.
+    ToleratedMismatch { command: u16, field: HeaderField },
+    /// Wait this much longer than the read delay before reading the response.
+    ExtraDelay { command: u16, delay: Duration },
+    /// The response carries this command class instead of the one of the report.
+    ResponseClass { command: u16, class: u8 },
+    /// The arguments of the response start this many bytes late, the bytes before are dropped.
+    ResponseOffset { command: u16, offset: usize },
+}
+
+/// Quirks of every supported model so far.
+pub const DEFAULT: &[Quirk] = &[
+    // bho
+    Quirk::ToleratedMismatch {
+        command: 0x0792,
+        field: HeaderField::RemainingPackets,
+    },
+    // max fan speed mode
+    Quirk::ToleratedMismatch {
+        command: 0x078f,
+        field: HeaderField::RemainingPackets,
+    },
+];
+
+pub(crate) fn tolerates(quirks: &[Quirk], command: u16, field: HeaderField) -> bool {
+    quirks.contains(&Quirk::ToleratedMismatch { command, field })
+}
+
+pub(crate) fn extra_delay(quirks: &[Quirk], command: u16) -> Duration {
+    quirks
+        .iter()
+        .filter_map(|quirk| match *quirk {
+            Quirk::ExtraDelay { command: c, delay } if c == command => Some(delay),
+            _ => None,
+        })
+        .sum()
+}
+
+pub(crate) fn response_class(quirks: &[Quirk], command: u16) -> Option<u8> {
+    quirks.iter().find_map(|quirk| match *quirk {
+        Quirk::ResponseClass { command: c, class } if c == command => Some(class),
+        _ => None,
+    })
+}
+
+pub(crate) fn response_offset(quirks: &[Quirk], command: u16) -> usize {
+    quirks
+        .iter()
+        .find_map(|quirk| match *quirk {
+            Quirk::ResponseOffset { command: c, offset } if c == command => Some(offset),
+            _ => None,
+        })
+        .unwrap_or(0)
+}
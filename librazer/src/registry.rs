@@ -0,0 +1,160 @@
+use crate::types::{
+    BatteryCare, CpuBoost, FanMode, GpuBoost, LightsAlwaysOn, MaxFanSpeedMode, PerfMode,
+};
+
+use std::fmt::Write;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Direction {
+    Get,
+    Set,
+}
+
+/// How a single argument byte is decoded.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArgKind {
+    /// Constant or unknown byte, not shown.
+    Reserved,
+    /// Selects what the command applies to (fan zone, cluster, led), shown in requests of getters.
+    Selector,
+    Byte,
+    PerfMode,
+    FanMode,
+    /// CPU or GPU boost, depending on the preceding cluster selector.
+    Boost,
+    /// Fan speed in units of 100 RPM.
+    Rpm,
+    MaxFanSpeedMode,
+    Power,
+    LogoMode,
+    LightsAlwaysOn,
+    BatteryCare,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Arg {
+    pub name: &'static str,
+    pub kind: ArgKind,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CommandSpec {
+    pub command: u16,
+    pub name: &'static str,
+    pub direction: Direction,
+    pub args: &'static [Arg],
+}
+
+macro_rules! args {
+    ($($name:literal: $kind:ident),* $(,)?) => {
+        &[$(Arg { name: $name, kind: ArgKind::$kind }),*]
+    };
+}
+
+const PERF_ARGS: &[Arg] = args!["": Reserved, "zone": Selector, "mode": PerfMode, "fan": FanMode];
+const BOOST_ARGS: &[Arg] = args!["": Reserved, "cluster": Selector, "boost": Boost];
+const FAN_RPM_ARGS: &[Arg] = args!["": Reserved, "zone": Selector, "rpm": Rpm];
+const LOGO_POWER_ARGS: &[Arg] = args!["": Reserved, "led": Selector, "power": Power];
+const LOGO_MODE_ARGS: &[Arg] = args!["": Reserved, "led": Selector, "mode": LogoMode];
+const BRIGHTNESS_ARGS: &[Arg] = args!["": Reserved, "led": Selector, "brightness": Byte];
+
+/// Every command known to librazer, the getter id is the setter id with bit 7 set.
+#[rustfmt::skip]
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec { command: 0x0d02, name: "perf mode", direction: Direction::Set, args: PERF_ARGS },
+    CommandSpec { command: 0x0d82, name: "perf mode", direction: Direction::Get, args: PERF_ARGS },
+    CommandSpec { command: 0x0d07, name: "boost", direction: Direction::Set, args: BOOST_ARGS },
+    CommandSpec { command: 0x0d87, name: "boost", direction: Direction::Get, args: BOOST_ARGS },
+    CommandSpec { command: 0x0d01, name: "fan rpm", direction: Direction::Set, args: FAN_RPM_ARGS },
+    CommandSpec { command: 0x0d81, name: "fan rpm", direction: Direction::Get, args: FAN_RPM_ARGS },
+    CommandSpec { command: 0x0d88, name: "fan actual rpm", direction: Direction::Get, args: FAN_RPM_ARGS },
+    CommandSpec { command: 0x070f, name: "max fan speed", direction: Direction::Set, args: args!["mode": MaxFanSpeedMode] },
+    CommandSpec { command: 0x078f, name: "max fan speed", direction: Direction::Get, args: args!["mode": MaxFanSpeedMode] },
+    CommandSpec { command: 0x0300, name: "logo power", direction: Direction::Set, args: LOGO_POWER_ARGS },
+    CommandSpec { command: 0x0380, name: "logo power", direction: Direction::Get, args: LOGO_POWER_ARGS },
+    CommandSpec { command: 0x0302, name: "logo mode", direction: Direction::Set, args: LOGO_MODE_ARGS },
+    CommandSpec { command: 0x0382, name: "logo mode", direction: Direction::Get, args: LOGO_MODE_ARGS },
+    CommandSpec { command: 0x0303, name: "keyboard brightness", direction: Direction::Set, args: BRIGHTNESS_ARGS },
+    CommandSpec { command: 0x0383, name: "keyboard brightness", direction: Direction::Get, args: BRIGHTNESS_ARGS },
+    CommandSpec { command: 0x0004, name: "lights always on", direction: Direction::Set, args: args!["mode": LightsAlwaysOn, "": Reserved] },
+    CommandSpec { command: 0x0084, name: "lights always on", direction: Direction::Get, args: args!["mode": LightsAlwaysOn, "": Reserved] },
+    CommandSpec { command: 0x0712, name: "battery care", direction: Direction::Set, args: args!["limit": BatteryCare] },
+    CommandSpec { command: 0x0792, name: "battery care", direction: Direction::Get, args: args!["limit": BatteryCare] },
+];
+
+pub fn lookup(command: u16) -> Option<&'static CommandSpec> {
+    COMMANDS.iter().find(|spec| spec.command == command)
+}
+
+fn decode<T: std::fmt::Debug>(value: crate::Result<T>, byte: u8) -> String {
+    match value {
+        Ok(value) => format!("{:?}", value),
+        Err(_) => format!("invalid({:#04x})", byte),
+    }
+}
+
+fn decode_arg(kind: ArgKind, byte: u8, selector: Option<u8>) -> String {
+    match kind {
+        ArgKind::Reserved | ArgKind::Selector | ArgKind::Byte => byte.to_string(),
+        ArgKind::PerfMode => decode(PerfMode::try_from(byte), byte),
+        ArgKind::FanMode => decode(FanMode::try_from(byte), byte),
+        ArgKind::Boost => match selector {
+            Some(0x01) => decode(CpuBoost::try_from(byte), byte),
+            Some(0x02) => decode(GpuBoost::try_from(byte), byte),
+            _ => format!("{:#04x}", byte),
+        },
+        ArgKind::Rpm => (byte as u16 * 100).to_string(),
+        ArgKind::MaxFanSpeedMode => decode(MaxFanSpeedMode::try_from(byte), byte),
+        ArgKind::Power => match byte {
+            0 => "Off".to_string(),
+            1 => "On".to_string(),
+            _ => format!("invalid({:#04x})", byte),
+        },
+        ArgKind::LogoMode => match byte {
+            0 => "Static".to_string(),
+            2 => "Breathing".to_string(),
+            _ => format!("invalid({:#04x})", byte),
+        },
+        ArgKind::LightsAlwaysOn => decode(LightsAlwaysOn::try_from(byte), byte),
+        ArgKind::BatteryCare => match BatteryCare::try_from(byte) {
+            Ok(BatteryCare::Disable) => "Disable".to_string(),
+            Ok(care) => format!("{}%", care.to_percent()),
+            Err(_) => format!("invalid({:#04x})", byte),
+        },
+    }
+}
+
+/// Render a command, e.g. `SET perf mode zone=2 mode=Custom fan=Manual`.
+///
+/// Getter requests carry placeholders instead of values, so only their selectors are shown.
+pub fn describe(command: u16, args: &[u8], is_request: bool) -> String {
+    let Some(spec) = lookup(command) else {
+        return format!("CMD {:#06x} args={:02x?}", command, args);
+    };
+
+    let mut text = format!(
+        "{} {}",
+        match spec.direction {
+            Direction::Get => "GET",
+            Direction::Set => "SET",
+        },
+        spec.name
+    );
+    let hide_values = is_request && spec.direction == Direction::Get;
+    let mut selector = None;
+    for (arg, &byte) in spec.args.iter().zip(args) {
+        match arg.kind {
+            ArgKind::Reserved => continue,
+            ArgKind::Selector => selector = Some(byte),
+            _ if hide_values => continue,
+            _ => (),
+        }
+        let _ = write!(
+            text,
+            " {}={}",
+            arg.name,
+            decode_arg(arg.kind, byte, selector)
+        );
+    }
+    text
+}
@@ -0,0 +1,206 @@
+use crate::device::Device;
+use crate::packet::Packet;
+use crate::transport::Transport;
+
+use crate::{Error, Result};
+
+use serde::{Deserialize, Serialize};
+use std::cell::{Cell, RefCell};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+
+/// One request/response pair, stored as a JSON line with hex encoded packets (no report id).
+/// The request is empty for the further response reports of a multi-report response.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Exchange {
+    request: String,
+    response: String,
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(hex: &str) -> Result<Vec<u8>> {
+    let invalid = || Error::InvalidData(format!("Invalid hex string {}", hex));
+    if !hex.is_ascii() || !hex.len().is_multiple_of(2) {
+        return Err(invalid());
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| invalid()))
+        .collect()
+}
+
+fn describe(bytes: &[u8]) -> String {
+    match <&[u8] as TryInto<Packet>>::try_into(bytes) {
+        Ok(packet) => packet.to_string(),
+        Err(_) => to_hex(bytes),
+    }
+}
+
+/// Transport wrapper that logs every request/response pair of the session to a file.
+pub struct RecordingTransport<T: Transport> {
+    inner: T,
+    log: RefCell<File>,
+    request: RefCell<Option<Vec<u8>>>,
+}
+
+impl<T: Transport> RecordingTransport<T> {
+    pub fn new(inner: T, path: impl AsRef<Path>) -> Result<RecordingTransport<T>> {
+        Ok(RecordingTransport::with_log(inner, create_log(path)?))
+    }
+
+    fn with_log(inner: T, log: File) -> RecordingTransport<T> {
+        RecordingTransport {
+            inner,
+            log: RefCell::new(log),
+            request: RefCell::new(None),
+        }
+    }
+}
+
+fn create_log(path: impl AsRef<Path>) -> Result<File> {
+    Ok(File::create(path.as_ref())?)
+}
+
+impl<T: Transport> Transport for RecordingTransport<T> {
+    fn send_feature_report(&self, data: &[u8]) -> Result<()> {
+        self.inner.send_feature_report(data)?;
+        // skip report id byte
+        *self.request.borrow_mut() = data.get(1..).map(<[u8]>::to_vec);
+        Ok(())
+    }
+
+    fn get_feature_report(&self, buf: &mut [u8]) -> Result<usize> {
+        let size = self.inner.get_feature_report(buf)?;
+        let request = self.request.borrow_mut().take().unwrap_or_default();
+        let exchange = Exchange {
+            request: to_hex(&request),
+            response: to_hex(buf.get(1..size).unwrap_or_default()),
+        };
+        let mut log = self.log.borrow_mut();
+        let line = serde_json::to_string(&exchange).map_err(|e| Error::Transport(e.into()))?;
+        writeln!(log, "{}", line)?;
+        log.flush()?;
+        Ok(size)
+    }
+}
+
+/// Transport that serves the responses of a recorded session back in order.
+///
+/// Requests are compared with the recording, ignoring the random transaction id, and the first
+/// divergence is reported as an error.
+pub struct ReplayTransport {
+    exchanges: Vec<Exchange>,
+    position: Cell<usize>,
+    pending: RefCell<Option<Vec<u8>>>,
+    // transaction id of the last request, for the response reports that follow it
+    request_id: Cell<u8>,
+}
+
+impl ReplayTransport {
+    pub fn open(path: impl AsRef<Path>) -> Result<ReplayTransport> {
+        let file = File::open(path.as_ref())?;
+        let exchanges = BufReader::new(file)
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !matches!(line, Ok(line) if line.trim().is_empty()))
+            .map(|(n, line)| {
+                serde_json::from_str(&line?).map_err(|e| {
+                    Error::InvalidData(format!("Invalid entry on line {}: {}", n + 1, e))
+                })
+            })
+            .collect::<Result<Vec<Exchange>>>()?;
+
+        Ok(ReplayTransport {
+            exchanges,
+            position: Cell::new(0),
+            pending: RefCell::new(None),
+            request_id: Cell::new(0),
+        })
+    }
+
+    fn response(&self, exchange: &Exchange) -> Result<Vec<u8>> {
+        let mut response = from_hex(&exchange.response)?;
+        if let Some(id) = response.get_mut(1) {
+            *id = self.request_id.get();
+        }
+        Ok(response)
+    }
+
+    /// Number of recorded exchanges that were not replayed yet.
+    pub fn remaining(&self) -> usize {
+        self.exchanges.len() - self.position.get()
+    }
+}
+
+impl Transport for ReplayTransport {
+    fn send_feature_report(&self, data: &[u8]) -> Result<()> {
+        // skip report id byte
+        let request = data.get(1..).unwrap_or_default();
+        let position = self.position.get();
+        let exchange = self
+            .exchanges
+            .get(position)
+            .ok_or_else(|| Error::ReplayDiverged {
+                position,
+                message: format!("recording has ended, got {}", describe(request)),
+            })?;
+
+        let expected = from_hex(&exchange.request)?;
+        // byte 1 is the transaction id, it is random for every session
+        let matches = expected.len() == request.len()
+            && expected.first() == request.first()
+            && expected.get(2..) == request.get(2..);
+        if !matches {
+            return Err(Error::ReplayDiverged {
+                position,
+                message: format!(
+                    "expected {}, got {}",
+                    describe(&expected),
+                    describe(request)
+                ),
+            });
+        }
+
+        self.request_id
+            .set(request.get(1).copied().unwrap_or_default());
+        *self.pending.borrow_mut() = Some(self.response(exchange)?);
+        self.position.set(position + 1);
+        Ok(())
+    }
+
+    fn get_feature_report(&self, buf: &mut [u8]) -> Result<usize> {
+        let pending = self.pending.borrow_mut().take();
+        let response = match pending {
+            Some(response) => response,
+            None => {
+                let position = self.position.get();
+                let exchange = self
+                    .exchanges
+                    .get(position)
+                    .filter(|exchange| exchange.request.is_empty())
+                    .ok_or(Error::Transport("No pending feature report".into()))?;
+                self.position.set(position + 1);
+                self.response(exchange)?
+            }
+        };
+        let size = 1 + response.len();
+        if buf.len() < size {
+            return Err(Error::Transport(
+                "Feature report buffer is too small".into(),
+            ));
+        }
+        buf[0] = 0; // report id
+        buf[1..size].copy_from_slice(&response);
+        Ok(size)
+    }
+}
+
+/// Record every transaction of `device` to `path`.
+pub fn record(device: Device, path: impl AsRef<Path>) -> Result<Device> {
+    let log = create_log(path)?;
+    Ok(device.map_transport(|transport| RecordingTransport::with_log(transport, log)))
+}
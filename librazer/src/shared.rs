@@ -0,0 +1,196 @@
+use crate::descriptor::Descriptor;
+use crate::device::Device;
+
+use std::collections::BinaryHeap;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{mpsc, Arc, Condvar, Mutex};
+use std::thread;
+
+/// Order in which queued transactions are run, higher first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    /// Polling, e.g. refreshing the fan speed.
+    Background,
+    /// Triggered by the user, e.g. a menu click.
+    Interactive,
+}
+
+type Job = Box<dyn FnOnce(&Device) + Send>;
+
+struct QueuedJob {
+    priority: Priority,
+    // FIFO within a priority
+    sequence: u64,
+    job: Job,
+}
+
+impl PartialEq for QueuedJob {
+    fn eq(&self, other: &Self) -> bool {
+        (self.priority, self.sequence) == (other.priority, other.sequence)
+    }
+}
+
+impl Eq for QueuedJob {}
+
+impl PartialOrd for QueuedJob {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for QueuedJob {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.priority
+            .cmp(&other.priority)
+            .then(other.sequence.cmp(&self.sequence))
+    }
+}
+
+#[derive(Default)]
+struct State {
+    jobs: BinaryHeap<QueuedJob>,
+    sequence: u64,
+    closed: bool,
+}
+
+#[derive(Default)]
+struct Queue {
+    state: Mutex<State>,
+    available: Condvar,
+}
+
+impl Queue {
+    fn push(&self, priority: Priority, job: Job) {
+        let mut state = self.state.lock().unwrap();
+        state.sequence += 1;
+        let sequence = state.sequence;
+        state.jobs.push(QueuedJob {
+            priority,
+            sequence,
+            job,
+        });
+        self.available.notify_one();
+    }
+
+    // None once the queue is closed and drained
+    fn pop(&self) -> Option<Job> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some(queued) = state.jobs.pop() {
+                return Some(queued.job);
+            }
+            if state.closed {
+                return None;
+            }
+            state = self.available.wait(state).unwrap();
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.state.lock().unwrap().jobs.len()
+    }
+
+    fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.available.notify_all();
+    }
+}
+
+struct Worker {
+    queue: Arc<Queue>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        self.queue.close();
+        // the last handle may be dropped by a transaction running on the worker itself
+        if let Some(thread) = self.thread.take() {
+            if thread.thread().id() != thread::current().id() {
+                let _ = thread.join();
+            }
+        }
+    }
+}
+
+/// `Device` handle that can be cloned and used from any thread.
+///
+/// The device is owned by a worker thread that runs one transaction at a time, so the
+/// write/read pairs of `Device::send` never interleave. Queued transactions run by priority,
+/// then in order of submission.
+#[derive(Clone)]
+pub struct SharedDevice {
+    info: Descriptor,
+    worker: Arc<Worker>,
+}
+
+impl SharedDevice {
+    pub fn new(device: Device) -> SharedDevice {
+        let info = device.info.clone();
+        let queue = Arc::new(Queue::default());
+        let thread = thread::Builder::new()
+            .name("librazer-device".to_string())
+            .spawn({
+                let queue = queue.clone();
+                move || {
+                    while let Some(job) = queue.pop() {
+                        job(&device);
+                    }
+                }
+            })
+            .expect("Failed to spawn device worker");
+
+        SharedDevice {
+            info,
+            worker: Arc::new(Worker {
+                queue,
+                thread: Some(thread),
+            }),
+        }
+    }
+
+    pub fn info(&self) -> &Descriptor {
+        &self.info
+    }
+
+    /// Transactions waiting for the worker, not counting the one it is running.
+    pub fn pending(&self) -> usize {
+        self.worker.queue.len()
+    }
+
+    /// Run `transaction` on the worker and wait for its result, e.g.
+    /// `shared.run(Priority::Interactive, |device| command::get_perf_mode(device))`.
+    ///
+    /// A panic in `transaction` is resumed on the calling thread.
+    pub fn run<R: Send + 'static>(
+        &self,
+        priority: Priority,
+        transaction: impl FnOnce(&Device) -> R + Send + 'static,
+    ) -> R {
+        let (sender, receiver) = mpsc::sync_channel(1);
+        self.submit(priority, transaction, move |result| {
+            let _ = sender.send(result);
+        });
+        match receiver.recv().expect("Device worker stopped") {
+            Ok(result) => result,
+            Err(payload) => panic::resume_unwind(payload),
+        }
+    }
+
+    /// Queue `transaction` and hand its outcome to `reply` on the worker thread.
+    pub(crate) fn submit<R: Send + 'static>(
+        &self,
+        priority: Priority,
+        transaction: impl FnOnce(&Device) -> R + Send + 'static,
+        reply: impl FnOnce(thread::Result<R>) + Send + 'static,
+    ) {
+        self.worker.queue.push(
+            priority,
+            Box::new(move |device| {
+                reply(panic::catch_unwind(AssertUnwindSafe(|| {
+                    transaction(device)
+                })))
+            }),
+        );
+    }
+}
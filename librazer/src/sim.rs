@@ -0,0 +1,234 @@
+use crate::descriptor::{self, Descriptor};
+use crate::device::Device;
+use crate::matching::matches_model;
+use crate::packet::{CommandStatus, Packet};
+use crate::transport::Transport;
+
+use crate::{Error, Result};
+use std::cell::RefCell;
+
+/// In-memory model of the Razer Blade EC.
+///
+/// Understands every command used by `command.rs`, keeps the written values and answers getters
+/// with them. Commands that belong to a feature the descriptor does not list, and unknown
+/// commands, are answered with the NotSupported status, malformed arguments with Failure.
+pub struct SimulatedBlade {
+    descriptor: Descriptor,
+    state: RefCell<State>,
+    pending: RefCell<Option<Packet>>,
+}
+
+#[derive(Debug, Clone)]
+struct State {
+    // (perf mode, fan mode) per fan zone
+    perf: [(u8, u8); 2],
+    // cpu, gpu
+    boost: [u8; 2],
+    // requested rpm / 100 per fan zone
+    fan_rpm: [u8; 2],
+    max_fan_speed: u8,
+    logo_power: u8,
+    logo_mode: u8,
+    kbd_brightness: u8,
+    lights_always_on: u8,
+    battery_care: u8,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self {
+            perf: [(0x00, 0x00); 2],
+            boost: [0x02, 0x02],
+            fan_rpm: [35, 35],
+            max_fan_speed: 0x00,
+            logo_power: 0x01,
+            logo_mode: 0x00,
+            kbd_brightness: 0x80,
+            lights_always_on: 0x00,
+            battery_care: 0x50,
+        }
+    }
+}
+
+impl State {
+    fn actual_rpm(&self, zone: usize) -> u8 {
+        match self.perf[zone] {
+            (_, 0x01) => self.fan_rpm[zone],
+            (0x05 | 0x06, _) => 20,
+            (0x00, _) => 30,
+            (0x07, _) => 50,
+            _ => 40,
+        }
+    }
+}
+
+fn feature_of(command: u16) -> Option<&'static [&'static str]> {
+    Some(match command {
+        0x0d02 | 0x0d82 => &["perf", "fan"],
+        0x0d07 | 0x0d87 => &["perf"],
+        0x0d01 | 0x0d81 | 0x0d88 | 0x070f | 0x078f => &["fan"],
+        0x0300 | 0x0380 | 0x0302 | 0x0382 => &["lid-logo"],
+        0x0303 | 0x0383 => &["kbd-backlight"],
+        0x0004 | 0x0084 => &["lights-always-on"],
+        0x0712 | 0x0792 => &["battery-care"],
+        _ => return None,
+    })
+}
+
+// 1-based zone / cluster index used by the EC
+fn zone(index: u8) -> Option<usize> {
+    match index {
+        1 | 2 => Some(index as usize - 1),
+        _ => None,
+    }
+}
+
+impl SimulatedBlade {
+    pub fn new(descriptor: Descriptor) -> SimulatedBlade {
+        SimulatedBlade {
+            descriptor,
+            state: RefCell::new(State::default()),
+            pending: RefCell::new(None),
+        }
+    }
+
+    fn supports(&self, command: u16) -> bool {
+        match feature_of(command) {
+            Some(features) => features
+                .iter()
+                .any(|f| self.descriptor.features.contains(f)),
+            None => self
+                .descriptor
+                .init
+                .iter()
+                .any(|step| step.command == command),
+        }
+    }
+
+    fn handle(&self, report: &Packet) -> Packet {
+        let command = report.command();
+        let args = report.get_args();
+
+        if !self.supports(command) {
+            return report.response(CommandStatus::NotSupported, report.data());
+        }
+
+        let mut state = self.state.borrow_mut();
+        let response = match (command, args) {
+            (0x0d02, &[_, z, perf, fan, ..]) => zone(z).map(|i| {
+                state.perf[i] = (perf, fan);
+                report.data().to_vec()
+            }),
+            (0x0d82, &[a, z, ..]) => zone(z).map(|i| vec![a, z, state.perf[i].0, state.perf[i].1]),
+            (0x0d07, &[_, c, boost, ..]) => zone(c).map(|i| {
+                state.boost[i] = boost;
+                report.data().to_vec()
+            }),
+            (0x0d87, &[a, c, ..]) => zone(c).map(|i| vec![a, c, state.boost[i]]),
+            (0x0d01, &[_, z, rpm, ..]) => zone(z).map(|i| {
+                state.fan_rpm[i] = rpm;
+                report.data().to_vec()
+            }),
+            (0x0d81, &[a, z, ..]) => zone(z).map(|i| vec![a, z, state.fan_rpm[i]]),
+            (0x0d88, &[a, z, ..]) => zone(z).map(|i| vec![a, z, state.actual_rpm(i)]),
+            (0x070f, &[mode, ..]) => {
+                state.max_fan_speed = mode;
+                Some(report.data().to_vec())
+            }
+            (0x078f, _) => Some(vec![state.max_fan_speed]),
+            (0x0300, &[a, 4, power, ..]) => {
+                state.logo_power = power;
+                Some(vec![a, 4, power])
+            }
+            (0x0380, &[a, 4, ..]) => Some(vec![a, 4, state.logo_power]),
+            (0x0302, &[a, 4, mode, ..]) => {
+                state.logo_mode = mode;
+                Some(vec![a, 4, mode])
+            }
+            (0x0382, &[a, 4, ..]) => Some(vec![a, 4, state.logo_mode]),
+            (0x0303, &[a, 5, brightness, ..]) => {
+                state.kbd_brightness = brightness;
+                Some(vec![a, 5, brightness])
+            }
+            (0x0383, &[a, 5, ..]) => Some(vec![a, 5, state.kbd_brightness]),
+            (0x0004, &[value, ..]) => {
+                state.lights_always_on = value;
+                Some(report.data().to_vec())
+            }
+            (0x0084, _) => Some(vec![state.lights_always_on, 0]),
+            (0x0712, &[value, ..]) => {
+                state.battery_care = value;
+                Some(report.data().to_vec())
+            }
+            (0x0792, _) => Some(vec![state.battery_care]),
+            // init commands are acknowledged as is
+            _ if feature_of(command).is_none() => Some(report.data().to_vec()),
+            _ => None,
+        };
+
+        match response {
+            Some(response) => report.response(CommandStatus::Successful, &response),
+            None => report.response(CommandStatus::Failure, report.data()),
+        }
+    }
+}
+
+impl Transport for SimulatedBlade {
+    fn send_feature_report(&self, data: &[u8]) -> Result<()> {
+        // skip report id byte
+        let report = <&[u8] as TryInto<Packet>>::try_into(data.get(1..).unwrap_or_default())?;
+        log::debug!("simulated report {:?}", report);
+        *self.pending.borrow_mut() = Some(self.handle(&report));
+        Ok(())
+    }
+
+    fn get_feature_report(&self, buf: &mut [u8]) -> Result<usize> {
+        let response = self
+            .pending
+            .borrow_mut()
+            .take()
+            .ok_or(Error::Transport("No pending feature report".into()))?;
+        let data: Vec<u8> = [0_u8; 1] // report id
+            .into_iter()
+            .chain(Into::<Vec<u8>>::into(&response))
+            .collect();
+        if buf.len() < data.len() {
+            return Err(Error::Transport(
+                "Feature report buffer is too small".into(),
+            ));
+        }
+        buf[..data.len()].copy_from_slice(&data);
+        Ok(data.len())
+    }
+}
+
+/// Find the supported model with the given model number prefix, e.g. `RZ09-0510S`.
+pub fn find(model_number_prefix: &str) -> Result<Descriptor> {
+    descriptor::supported()
+        .iter()
+        .find(|supported| matches_model(supported.model_number_prefix, model_number_prefix))
+        .cloned()
+        .ok_or_else(|| {
+            Error::InvalidArgument(format!(
+                "Unknown model {}, expected one of {:?}",
+                model_number_prefix,
+                descriptor::supported()
+                    .iter()
+                    .map(|supported| supported.model_number_prefix)
+                    .collect::<Vec<_>>()
+            ))
+        })
+}
+
+/// Open a simulated laptop of the given model and run its init sequence, like `Device::new`.
+pub fn open(model_number_prefix: &str) -> Result<Device> {
+    open_descriptor(find(model_number_prefix)?)
+}
+
+/// Open a simulated laptop of `descriptor`, e.g. one found with `find` and given other timings,
+/// and run its init sequence.
+pub fn open_descriptor(descriptor: Descriptor) -> Result<Device> {
+    let device = Device::with_transport(descriptor.clone(), SimulatedBlade::new(descriptor));
+    device.init()?;
+    Ok(device)
+}
@@ -0,0 +1,356 @@
+use crate::command;
+use crate::device::Device;
+use crate::types::{
+    self, BatteryCare, CpuBoost, FanMode, FanZone, GpuBoost, LightsAlwaysOn, LogoMode,
+};
+use crate::{Error, Result};
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum FanSpeed {
+    Auto,
+    Manual(u16),
+}
+
+/// `types::PerfMode` with the boosts that only apply to `Custom`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum PerfMode {
+    Battery,
+    Silent,
+    Balanced,
+    Performance,
+    Hyperboost,
+    Custom(CpuBoost, GpuBoost),
+}
+
+impl PerfMode {
+    pub fn mode(&self) -> types::PerfMode {
+        match self {
+            PerfMode::Battery => types::PerfMode::Battery,
+            PerfMode::Silent => types::PerfMode::Silent,
+            PerfMode::Balanced => types::PerfMode::Balanced,
+            PerfMode::Performance => types::PerfMode::Performance,
+            PerfMode::Hyperboost => types::PerfMode::Hyperboost,
+            PerfMode::Custom(..) => types::PerfMode::Custom,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LightsMode {
+    pub logo_mode: LogoMode,
+    pub keyboard_brightness: u8,
+    pub always_on: LightsAlwaysOn,
+}
+
+/// Every setting of the laptop, read and written as a whole.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct DeviceState {
+    pub perf_mode: PerfMode,
+    pub lights_mode: LightsMode,
+    pub battery_care: BatteryCare,
+    pub fan_speed: FanSpeed,
+}
+
+/// A single setter, the unit `DeviceState::apply_changes` sends.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Change {
+    /// Also puts the fans back to `FanMode::Auto`.
+    PerfMode(types::PerfMode),
+    CpuBoost(CpuBoost),
+    GpuBoost(GpuBoost),
+    FanMode(FanMode),
+    FanRpm(u16),
+    LogoMode(LogoMode),
+    KeyboardBrightness(u8),
+    LightsAlwaysOn(LightsAlwaysOn),
+    BatteryCare(BatteryCare),
+}
+
+impl Change {
+    pub fn apply(&self, device: &Device) -> Result<()> {
+        match *self {
+            Change::PerfMode(mode) => command::set_perf_mode(device, mode),
+            Change::CpuBoost(boost) => command::set_cpu_boost(device, boost),
+            Change::GpuBoost(boost) => command::set_gpu_boost(device, boost),
+            Change::FanMode(mode) => command::set_fan_mode(device, mode),
+            Change::FanRpm(rpm) => command::set_fan_rpm(device, rpm, false),
+            Change::LogoMode(mode) => command::set_logo_mode(device, mode),
+            Change::KeyboardBrightness(brightness) => {
+                command::set_keyboard_brightness(device, brightness)
+            }
+            Change::LightsAlwaysOn(lights_always_on) => {
+                command::set_lights_always_on(device, lights_always_on)
+            }
+            Change::BatteryCare(mode) => command::set_battery_care(device, mode),
+        }
+    }
+}
+
+/// What became of a change sent by `DeviceState::apply_transaction`.
+#[derive(Debug)]
+pub enum Outcome {
+    Applied,
+    Failed(Error),
+    /// Not sent, an earlier change failed.
+    Skipped,
+    /// Applied, then undone by the rollback.
+    RolledBack,
+}
+
+#[derive(Debug)]
+pub struct Step {
+    pub change: Change,
+    pub outcome: Outcome,
+}
+
+/// Outcome of every change of a transaction, and of the rollback after one failed.
+#[derive(Debug, Default)]
+pub struct ApplyReport {
+    pub steps: Vec<Step>,
+    /// Changes sent to restore the snapshot, empty unless a step failed.
+    pub rollback: Vec<Step>,
+}
+
+impl ApplyReport {
+    /// The change of the transaction that failed, if any.
+    pub fn failed(&self) -> Option<&Step> {
+        self.steps
+            .iter()
+            .find(|step| matches!(step.outcome, Outcome::Failed(_)))
+    }
+
+    /// Whether a change failed and the device is back in the snapshot.
+    pub fn rolled_back(&self) -> bool {
+        self.failed().is_some()
+            && self
+                .rollback
+                .iter()
+                .all(|step| matches!(step.outcome, Outcome::Applied))
+    }
+}
+
+impl std::fmt::Display for ApplyReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Some(Step {
+            change,
+            outcome: Outcome::Failed(e),
+        }) = self.failed()
+        else {
+            return write!(f, "applied {} changes", self.steps.len());
+        };
+        write!(f, "{:?} failed: {}", change, e)?;
+        if self.rolled_back() {
+            return write!(f, ", rolled back");
+        }
+        self.rollback
+            .iter()
+            .try_for_each(|step| match &step.outcome {
+                Outcome::Failed(e) => write!(f, ", rollback of {:?} failed: {}", step.change, e),
+                _ => Ok(()),
+            })
+    }
+}
+
+impl DeviceState {
+    pub fn read(device: &Device) -> Result<Self> {
+        let (mode, fan_mode) = command::get_perf_mode(device)?;
+        let perf_mode = match mode {
+            types::PerfMode::Battery => PerfMode::Battery,
+            types::PerfMode::Silent => PerfMode::Silent,
+            types::PerfMode::Balanced => PerfMode::Balanced,
+            types::PerfMode::Performance => PerfMode::Performance,
+            types::PerfMode::Hyperboost => PerfMode::Hyperboost,
+            types::PerfMode::Custom => PerfMode::Custom(
+                command::get_cpu_boost(device)?,
+                command::get_gpu_boost(device)?,
+            ),
+        };
+
+        let fan_speed = match fan_mode {
+            FanMode::Auto => FanSpeed::Auto,
+            FanMode::Manual => FanSpeed::Manual(command::get_fan_rpm(device, FanZone::Zone1)?),
+        };
+
+        let lights_mode = LightsMode {
+            logo_mode: command::get_logo_mode(device)?,
+            keyboard_brightness: command::get_keyboard_brightness(device)?,
+            always_on: command::get_lights_always_on(device)?,
+        };
+
+        Ok(Self {
+            perf_mode,
+            lights_mode,
+            battery_care: command::get_battery_care(device)?,
+            fan_speed,
+        })
+    }
+
+    /// Changes that turn a laptop in this state into `other`, in the order they have to be
+    /// sent: the perf mode before the boosts it enables, the fan mode before the rpm.
+    pub fn diff(&self, other: &DeviceState) -> Vec<Change> {
+        let mut changes = Vec::new();
+
+        let mode_changed = self.perf_mode.mode() != other.perf_mode.mode();
+        if mode_changed {
+            changes.push(Change::PerfMode(other.perf_mode.mode()));
+        }
+        if let PerfMode::Custom(cpu_boost, gpu_boost) = other.perf_mode {
+            let (cpu, gpu) = match self.perf_mode {
+                PerfMode::Custom(cpu, gpu) if !mode_changed => (Some(cpu), Some(gpu)),
+                _ => (None, None),
+            };
+            if cpu != Some(cpu_boost) {
+                changes.push(Change::CpuBoost(cpu_boost));
+            }
+            if gpu != Some(gpu_boost) {
+                changes.push(Change::GpuBoost(gpu_boost));
+            }
+        }
+
+        // setting the perf mode resets the fans to auto
+        let fan_speed = match mode_changed {
+            true => FanSpeed::Auto,
+            false => self.fan_speed,
+        };
+        match (fan_speed, other.fan_speed) {
+            (FanSpeed::Auto, FanSpeed::Manual(rpm)) => {
+                changes.push(Change::FanMode(FanMode::Manual));
+                changes.push(Change::FanRpm(rpm));
+            }
+            (FanSpeed::Manual(_), FanSpeed::Auto) => changes.push(Change::FanMode(FanMode::Auto)),
+            (FanSpeed::Manual(current), FanSpeed::Manual(rpm)) if current != rpm => {
+                changes.push(Change::FanRpm(rpm))
+            }
+            _ => (),
+        }
+
+        let (lights, other_lights) = (self.lights_mode, other.lights_mode);
+        if lights.logo_mode != other_lights.logo_mode {
+            changes.push(Change::LogoMode(other_lights.logo_mode));
+        }
+        if lights.keyboard_brightness != other_lights.keyboard_brightness {
+            changes.push(Change::KeyboardBrightness(other_lights.keyboard_brightness));
+        }
+        if lights.always_on != other_lights.always_on {
+            changes.push(Change::LightsAlwaysOn(other_lights.always_on));
+        }
+        if self.battery_care != other.battery_care {
+            changes.push(Change::BatteryCare(other.battery_care));
+        }
+        changes
+    }
+
+    /// Every change it takes to put a laptop in an unknown state into this one.
+    pub fn changes(&self) -> Vec<Change> {
+        let mut changes = vec![Change::PerfMode(self.perf_mode.mode())];
+        if let PerfMode::Custom(cpu_boost, gpu_boost) = self.perf_mode {
+            changes.push(Change::CpuBoost(cpu_boost));
+            changes.push(Change::GpuBoost(gpu_boost));
+        }
+        match self.fan_speed {
+            FanSpeed::Auto => changes.push(Change::FanMode(FanMode::Auto)),
+            FanSpeed::Manual(rpm) => {
+                changes.push(Change::FanMode(FanMode::Manual));
+                changes.push(Change::FanRpm(rpm));
+            }
+        }
+        changes.extend([
+            Change::LogoMode(self.lights_mode.logo_mode),
+            Change::KeyboardBrightness(self.lights_mode.keyboard_brightness),
+            Change::LightsAlwaysOn(self.lights_mode.always_on),
+            Change::BatteryCare(self.battery_care),
+        ]);
+        changes
+    }
+
+    /// Send `changes` in order, stopping at the first error.
+    pub fn apply_changes(device: &Device, changes: &[Change]) -> Result<()> {
+        changes.iter().try_for_each(|change| change.apply(device))
+    }
+
+    /// Send the changes from `self` to `target` under the device lock, and restore `self` when
+    /// one fails.
+    ///
+    /// `self` is the snapshot to go back to, e.g. from `DeviceState::read`. After a failed change
+    /// the state is read again and the difference to the snapshot is sent, every setting of the
+    /// snapshot if the state can't be read. Fails with `Error::Transaction` carrying the report.
+    pub fn apply_transaction(&self, device: &Device, target: &DeviceState) -> Result<ApplyReport> {
+        let _lock = device.lock()?;
+        let mut report = ApplyReport::default();
+        let mut changes = self.diff(target).into_iter();
+        for change in changes.by_ref() {
+            match change.apply(device) {
+                Ok(()) => report.steps.push(Step {
+                    change,
+                    outcome: Outcome::Applied,
+                }),
+                Err(e) => {
+                    report.steps.push(Step {
+                        change,
+                        outcome: Outcome::Failed(e),
+                    });
+                    break;
+                }
+            }
+        }
+        if report.failed().is_none() {
+            return Ok(report);
+        }
+        report.steps.extend(changes.map(|change| Step {
+            change,
+            outcome: Outcome::Skipped,
+        }));
+
+        let restore = match DeviceState::read(device) {
+            Ok(current) => current.diff(self),
+            Err(e) => {
+                log::warn!(
+                    "Failed to read the state to roll back, restoring all of it: {}",
+                    e
+                );
+                self.changes()
+            }
+        };
+        // best effort, a failed change does not keep the others from restoring what they can
+        report.rollback = restore
+            .into_iter()
+            .map(|change| Step {
+                change,
+                outcome: match change.apply(device) {
+                    Ok(()) => Outcome::Applied,
+                    Err(e) => Outcome::Failed(e),
+                },
+            })
+            .collect();
+        if report.rolled_back() {
+            for step in &mut report.steps {
+                if matches!(step.outcome, Outcome::Applied) {
+                    step.outcome = Outcome::RolledBack;
+                }
+            }
+        }
+        Err(Error::Transaction(Box::new(report)))
+    }
+
+    /// Send every setting, see `changes`.
+    pub fn apply(&self, device: &Device) -> Result<()> {
+        DeviceState::apply_changes(device, &self.changes())
+    }
+}
+
+impl Default for DeviceState {
+    fn default() -> Self {
+        Self {
+            perf_mode: PerfMode::Performance,
+            lights_mode: LightsMode {
+                logo_mode: LogoMode::Off,
+                keyboard_brightness: 0,
+                always_on: LightsAlwaysOn::Disable,
+            },
+            battery_care: BatteryCare::Percent80,
+            fan_speed: FanSpeed::Auto,
+        }
+    }
+}
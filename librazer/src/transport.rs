@@ -0,0 +1,32 @@
+use crate::Result;
+
+/// Transport moves raw feature reports between the host and the device.
+///
+/// Buffers follow hidapi conventions: the first byte is the report id (always 0 for Razer
+/// laptops), followed by the serialized `Packet`.
+pub trait Transport: Send {
+    fn send_feature_report(&self, data: &[u8]) -> Result<()>;
+
+    /// Fill `buf` with the pending feature report and return the number of bytes read.
+    fn get_feature_report(&self, buf: &mut [u8]) -> Result<usize>;
+}
+
+impl Transport for hidapi::HidDevice {
+    fn send_feature_report(&self, data: &[u8]) -> Result<()> {
+        Ok(hidapi::HidDevice::send_feature_report(self, data)?)
+    }
+
+    fn get_feature_report(&self, buf: &mut [u8]) -> Result<usize> {
+        Ok(hidapi::HidDevice::get_feature_report(self, buf)?)
+    }
+}
+
+impl<T: Transport + ?Sized> Transport for Box<T> {
+    fn send_feature_report(&self, data: &[u8]) -> Result<()> {
+        (**self).send_feature_report(data)
+    }
+
+    fn get_feature_report(&self, buf: &mut [u8]) -> Result<usize> {
+        (**self).get_feature_report(buf)
+    }
+}
@@ -1,4 +1,5 @@
-use anyhow::{bail, Result};
+use crate::{Error, Result};
+
 use clap::ValueEnum;
 use serde::{Deserialize, Serialize};
 use strum_macros::{EnumIter, EnumString};
@@ -70,33 +71,36 @@ pub enum LightsAlwaysOn {
 
 #[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum BatteryCare {
-    Percent50 = 0xB2,      // 50% limit (178 decimal) - VERIFIED from BIOS
-    Percent55 = 0xB7,      // 55% limit (183 decimal) - VERIFIED works
-    Percent60 = 0xBC,      // 60% limit (188 decimal) - VERIFIED works
-    Percent65 = 0xC1,      // 65% limit (193 decimal) - calculated from pattern
-    Percent70 = 0xC6,      // 70% limit (198 decimal) - calculated from pattern
-    Percent75 = 0xCB,      // 75% limit (203 decimal) - calculated from pattern
-    Percent80 = 0xD0,      // 80% limit (208 decimal) - VERIFIED from protocol capture
-    Disable = 0x50,        // 100% - no limit (80 decimal) - VERIFIED
+    Percent50 = 0xB2, // 50% limit (178 decimal) - VERIFIED from BIOS
+    Percent55 = 0xB7, // 55% limit (183 decimal) - VERIFIED works
+    Percent60 = 0xBC, // 60% limit (188 decimal) - VERIFIED works
+    Percent65 = 0xC1, // 65% limit (193 decimal) - calculated from pattern
+    Percent70 = 0xC6, // 70% limit (198 decimal) - calculated from pattern
+    Percent75 = 0xCB, // 75% limit (203 decimal) - calculated from pattern
+    Percent80 = 0xD0, // 80% limit (208 decimal) - VERIFIED from protocol capture
+    Disable = 0x50,   // 100% - no limit (80 decimal) - VERIFIED
 }
 
 impl TryFrom<u8> for GpuBoost {
-    type Error = anyhow::Error;
+    type Error = Error;
 
-    fn try_from(value: u8) -> Result<Self, Self::Error> {
+    fn try_from(value: u8) -> Result<Self> {
         match value {
             0 => Ok(Self::Low),
             1 => Ok(Self::Medium),
             2 => Ok(Self::High),
-            _ => bail!("Failed to convert {} to GpuBoost", value),
+            _ => Err(Error::InvalidValue {
+                kind: "GpuBoost",
+                value,
+            }),
         }
     }
 }
 
 impl TryFrom<u8> for PerfMode {
-    type Error = anyhow::Error;
+    type Error = Error;
 
-    fn try_from(perf_mode: u8) -> Result<Self, Self::Error> {
+    fn try_from(perf_mode: u8) -> Result<Self> {
         match perf_mode {
             0 => Ok(Self::Balanced),
             2 => Ok(Self::Performance),
@@ -104,54 +108,66 @@ impl TryFrom<u8> for PerfMode {
             5 => Ok(Self::Silent),
             6 => Ok(Self::Battery),
             7 => Ok(Self::Hyperboost),
-            _ => bail!("Failed to convert {} to PerformanceMode", perf_mode),
+            _ => Err(Error::InvalidValue {
+                kind: "PerfMode",
+                value: perf_mode,
+            }),
         }
     }
 }
 
 impl TryFrom<u8> for FanMode {
-    type Error = anyhow::Error;
+    type Error = Error;
 
-    fn try_from(fan_mode: u8) -> Result<Self, Self::Error> {
+    fn try_from(fan_mode: u8) -> Result<Self> {
         match fan_mode {
             0 => Ok(Self::Auto),
             1 => Ok(Self::Manual),
-            _ => bail!("Failed to convert {} to FanMode", fan_mode),
+            _ => Err(Error::InvalidValue {
+                kind: "FanMode",
+                value: fan_mode,
+            }),
         }
     }
 }
 
 impl TryFrom<u8> for CpuBoost {
-    type Error = anyhow::Error;
+    type Error = Error;
 
-    fn try_from(value: u8) -> Result<Self, Self::Error> {
+    fn try_from(value: u8) -> Result<Self> {
         match value {
             0 => Ok(Self::Low),
             1 => Ok(Self::Medium),
             2 => Ok(Self::High),
             3 => Ok(Self::Boost),
             4 => Ok(Self::Undervolt),
-            _ => bail!("Failed to convert {} to CpuBoost", value),
+            _ => Err(Error::InvalidValue {
+                kind: "CpuBoost",
+                value,
+            }),
         }
     }
 }
 
 impl TryFrom<u8> for LightsAlwaysOn {
-    type Error = anyhow::Error;
+    type Error = Error;
 
-    fn try_from(value: u8) -> Result<Self, Self::Error> {
+    fn try_from(value: u8) -> Result<Self> {
         match value {
             0 => Ok(LightsAlwaysOn::Disable),
             3 => Ok(LightsAlwaysOn::Enable),
-            _ => bail!("Failed to convert {} to LightsAlwaysOn", value),
+            _ => Err(Error::InvalidValue {
+                kind: "LightsAlwaysOn",
+                value,
+            }),
         }
     }
 }
 
 impl TryFrom<u8> for BatteryCare {
-    type Error = anyhow::Error;
+    type Error = Error;
 
-    fn try_from(value: u8) -> Result<Self, Self::Error> {
+    fn try_from(value: u8) -> Result<Self> {
         match value {
             0xB2 => Ok(BatteryCare::Percent50),
             0xB7 => Ok(BatteryCare::Percent55),
@@ -161,7 +177,10 @@ impl TryFrom<u8> for BatteryCare {
             0xCB => Ok(BatteryCare::Percent75),
             0xD0 => Ok(BatteryCare::Percent80),
             0x50 => Ok(BatteryCare::Disable),
-            _ => bail!("Failed to convert {:#x} to BatteryCare", value),
+            _ => Err(Error::InvalidValue {
+                kind: "BatteryCare",
+                value,
+            }),
         }
     }
 }
@@ -179,7 +198,10 @@ impl BatteryCare {
             73..=77 => Ok(BatteryCare::Percent75),
             78..=90 => Ok(BatteryCare::Percent80),
             91..=100 => Ok(BatteryCare::Disable),
-            _ => bail!("Invalid battery care percentage: {} (must be 50-100)", percent),
+            _ => Err(Error::InvalidArgument(format!(
+                "Invalid battery care percentage: {} (must be 50-100)",
+                percent
+            ))),
         }
     }
 
@@ -199,13 +221,16 @@ impl BatteryCare {
 }
 
 impl TryFrom<u8> for MaxFanSpeedMode {
-    type Error = anyhow::Error;
+    type Error = Error;
 
-    fn try_from(value: u8) -> Result<Self, Self::Error> {
+    fn try_from(value: u8) -> Result<Self> {
         match value {
             0x02 => Ok(MaxFanSpeedMode::Enable),
             0x00 => Ok(MaxFanSpeedMode::Disable),
-            _ => bail!("Failed to convert {} to MaxFanSpeedMode", value),
+            _ => Err(Error::InvalidValue {
+                kind: "MaxFanSpeedMode",
+                value,
+            }),
         }
     }
 }
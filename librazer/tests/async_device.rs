@@ -0,0 +1,37 @@
+#![cfg(feature = "async")]
+
+mod common;
+
+use common::{FakeTransport, BUSY, SUCCESSFUL};
+use librazer::async_device::AsyncDevice;
+
+use std::time::{Duration, Instant};
+
+#[tokio::test]
+async fn busy_then_success_frees_the_worker_between_attempts() {
+    let transport = FakeTransport::scripted(&[BUSY, SUCCESSFUL]);
+    let retry_delay = Duration::from_millis(300);
+    let policy = common::policy()
+        .max_attempts(2)
+        .backoff(retry_delay, 1, retry_delay);
+    let device = AsyncDevice::new(common::device(transport.clone()).with_policy(policy));
+
+    let other = device.clone();
+    let (result, waited) = tokio::join!(device.set_keyboard_brightness(0x20), async {
+        // queued while the first attempt is waiting to be retried
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let start = Instant::now();
+        other.run(|_| ()).await;
+        start.elapsed()
+    });
+
+    result.unwrap();
+    assert!(
+        waited < Duration::from_millis(200),
+        "worker was held for {:?}",
+        waited
+    );
+    assert_eq!(transport.reports(), 2);
+    let stats = device.run(|device| device.stats()).await;
+    assert_eq!(stats[&0x0303].retries, 1);
+}
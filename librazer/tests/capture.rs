@@ -0,0 +1,177 @@
+use librazer::capture::{self, CapturedReport};
+use librazer::{Error, Packet};
+
+const CSV: &str = include_str!("../../data/wireshark_dump_raw.csv");
+const ANNOTATIONS: &str = include_str!("../../data/annotations.csv");
+
+/// Feature report as captured on the wire, without the report id.
+fn report(command: u16, args: &[u8]) -> Vec<u8> {
+    (&Packet::new(command, args)).into()
+}
+
+/// usbmon capture, the 48 byte pseudo header of every frame carries the captured length.
+fn pcap(reports: &[Vec<u8>]) -> Vec<u8> {
+    let mut pcap = vec![0xd4, 0xc3, 0xb2, 0xa1, 2, 0, 4, 0];
+    pcap.extend([0; 8]);
+    pcap.extend(65535_u32.to_le_bytes());
+    pcap.extend(189_u32.to_le_bytes());
+    for (second, report) in reports.iter().enumerate() {
+        let mut frame = vec![0; 48];
+        frame[36..40].copy_from_slice(&(report.len() as u32).to_le_bytes());
+        frame.extend(report);
+        pcap.extend((second as u32).to_le_bytes());
+        pcap.extend(500_000_u32.to_le_bytes());
+        pcap.extend((frame.len() as u32).to_le_bytes());
+        pcap.extend((frame.len() as u32).to_le_bytes());
+        pcap.extend(frame);
+    }
+    pcap
+}
+
+fn block(block_type: u32, body: &[u8]) -> Vec<u8> {
+    let len = (12 + body.len().next_multiple_of(4)) as u32;
+    let mut block = [block_type.to_le_bytes(), len.to_le_bytes()].concat();
+    block.extend(body);
+    block.resize(len as usize - 4, 0);
+    block.extend(len.to_le_bytes());
+    block
+}
+
+/// USBPcap capture of SET_REPORT control transfers, the setup packet precedes the report.
+fn pcapng(reports: &[Vec<u8>]) -> Vec<u8> {
+    let mut section = vec![0x4d, 0x3c, 0x2b, 0x1a, 1, 0, 0, 0];
+    section.extend(u64::MAX.to_le_bytes());
+    let mut interface = 249_u16.to_le_bytes().to_vec();
+    interface.extend([0; 2]);
+    interface.extend(65535_u32.to_le_bytes());
+
+    let mut pcapng = [block(0x0a0d0d0a, &section), block(1, &interface)].concat();
+    for (second, report) in reports.iter().enumerate() {
+        let mut frame = vec![0; 28];
+        frame[0..2].copy_from_slice(&28_u16.to_le_bytes());
+        frame[22] = 2; // control transfer
+        frame[27] = 0; // setup stage
+        frame.extend([0x21, 0x09, 0x00, 0x03, 0x00, 0x00, 0x5a, 0x00]);
+        frame.extend(report);
+
+        let timestamp = second as u64 * 1_000_000;
+        let mut packet = 0_u32.to_le_bytes().to_vec();
+        packet.extend(((timestamp >> 32) as u32).to_le_bytes());
+        packet.extend((timestamp as u32).to_le_bytes());
+        packet.extend((frame.len() as u32).to_le_bytes());
+        packet.extend((frame.len() as u32).to_le_bytes());
+        packet.extend(frame);
+        pcapng.extend(block(6, &packet));
+    }
+    pcapng
+}
+
+fn captured(time: f64, command: u16, args: &[u8]) -> CapturedReport {
+    CapturedReport {
+        time,
+        command,
+        args: args.to_vec(),
+        crc_mismatch: false,
+    }
+}
+
+#[test]
+fn wireshark_csv_joins_annotations() {
+    let reports = capture::read_csv(CSV).unwrap();
+    // one row carries a single byte instead of a report
+    assert_eq!(reports.len(), 127);
+    assert_eq!(reports[0].command, 0x030b);
+
+    let annotations = capture::read_annotations(ANNOTATIONS).unwrap();
+    let table = capture::action_table(&reports, &annotations).unwrap();
+    let perf_modes: Vec<_> = table
+        .iter()
+        .take(8)
+        .map(|row| (row.action.as_str(), row.command, row.args[2]))
+        .collect();
+    assert_eq!(
+        perf_modes,
+        [
+            ("set balanced auto fan mode", 0x0d02, 0x00),
+            ("", 0x0d02, 0x00),
+            ("set performance auto fan mode", 0x0d02, 0x02),
+            ("", 0x0d02, 0x02),
+            ("set silent mode", 0x0d02, 0x05),
+            ("", 0x0d02, 0x05),
+            ("set custom mode, cpu boost, gpu high", 0x0d02, 0x04),
+            ("", 0x0d02, 0x04),
+        ]
+    );
+}
+
+#[test]
+fn usbmon_pcap_is_read() {
+    let reports = [
+        report(0x0d02, &[0x01, 0x01, 0x04, 0x00]),
+        report(0x0303, &[0x01, 0x05, 0x00]),
+    ];
+    assert_eq!(
+        capture::read_pcap(&pcap(&reports)).unwrap(),
+        [
+            captured(0.0, 0x0d02, &[0x01, 0x01, 0x04, 0x00]),
+            captured(1.0, 0x0303, &[0x01, 0x05, 0x00]),
+        ]
+    );
+}
+
+#[test]
+fn usbpcap_pcapng_is_read() {
+    let reports = [
+        report(0x0d02, &[0x01, 0x02, 0x04, 0x00]),
+        report(0x0792, &[]),
+    ];
+    assert_eq!(
+        capture::read_pcapng(&pcapng(&reports)).unwrap(),
+        [
+            captured(0.0, 0x0d02, &[0x01, 0x02, 0x04, 0x00]),
+            captured(1.0, 0x0792, &[]),
+        ]
+    );
+}
+
+#[test]
+fn truncated_records_are_errors() {
+    let reports = [report(0x0d02, &[0x01, 0x01, 0x04, 0x00])];
+
+    let pcap = pcap(&reports);
+    for len in [10, pcap.len() - 1] {
+        assert!(matches!(
+            capture::read_pcap(&pcap[..len]),
+            Err(Error::InvalidData(_))
+        ));
+    }
+
+    let pcapng = pcapng(&reports);
+    for len in [pcapng.len() - 1, pcapng.len() - 100] {
+        assert!(matches!(
+            capture::read_pcapng(&pcapng[..len]),
+            Err(Error::InvalidData(_))
+        ));
+    }
+}
+
+#[test]
+fn report_with_wrong_crc_is_marked() {
+    let mut corrupted = report(0x0303, &[0x01, 0x05, 0x00]);
+    corrupted[10] ^= 0x01;
+    let reports = capture::read_pcap(&pcap(&[corrupted])).unwrap();
+    assert_eq!(
+        reports,
+        [CapturedReport {
+            crc_mismatch: true,
+            ..captured(0.0, 0x0303, &[0x01, 0x05, 0x01])
+        }]
+    );
+
+    let rows = capture::action_table(
+        &reports,
+        &capture::read_annotations("0 \"set brightness\"").unwrap(),
+    )
+    .unwrap();
+    assert!(capture::to_markdown(&rows).contains("| 0303 (bad crc) |"));
+}
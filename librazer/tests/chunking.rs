@@ -0,0 +1,165 @@
+mod common;
+
+use common::{ARGS, CRC, DATA_SIZE, REMAINING_PACKETS, STATUS, SUCCESSFUL};
+use librazer::device::Device;
+use librazer::replay::{self, ReplayTransport};
+use librazer::transport::Transport;
+use librazer::{command, Error, Packet, Result};
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+const BULK_SET: u16 = 0x0f02;
+const BULK_GET: u16 = 0x0f82;
+
+/// Collects the payload of every report, echoes them, and answers `BULK_GET` with `reply`
+/// spread over as many response reports as it takes.
+#[derive(Default)]
+struct BulkEc {
+    headers: Vec<(u16, u8)>,
+    received: Vec<u8>,
+    reply: Vec<u8>,
+    // remaining packets of the continuation reports are off by one
+    out_of_order: bool,
+    responses: VecDeque<Vec<u8>>,
+}
+
+#[derive(Clone, Default)]
+struct FakeTransport(Arc<Mutex<BulkEc>>);
+
+// successful response carrying `data`, without the report id
+fn seal(report: &mut [u8], remaining: u16, data: &[u8]) {
+    report[STATUS] = SUCCESSFUL;
+    report[REMAINING_PACKETS..REMAINING_PACKETS + 2].copy_from_slice(&remaining.to_be_bytes());
+    report[DATA_SIZE] = data.len() as u8;
+    report[ARGS..CRC].fill(0);
+    report[ARGS..ARGS + data.len()].copy_from_slice(data);
+    common::seal(report);
+}
+
+impl Transport for FakeTransport {
+    fn send_feature_report(&self, data: &[u8]) -> Result<()> {
+        let mut ec = self.0.lock().unwrap();
+        // skip report id byte
+        let report = Packet::try_from(&data[1..])?;
+        ec.headers
+            .push((report.remaining_packets(), report.data_size() as u8));
+        ec.received.extend_from_slice(report.data());
+
+        let mut echo = data[1..].to_vec();
+        echo[STATUS] = SUCCESSFUL;
+        if report.command() != BULK_GET || report.remaining_packets() > 0 {
+            ec.responses.push_back(echo);
+            return Ok(());
+        }
+        let chunks: Vec<Vec<u8>> = ec.reply.chunks(80).map(<[u8]>::to_vec).collect();
+        let skew = ec.out_of_order as u16;
+        for (i, chunk) in chunks.iter().enumerate() {
+            let mut response = echo.clone();
+            let remaining = (chunks.len() - 1 - i) as u16;
+            seal(
+                &mut response,
+                if i > 0 { remaining + skew } else { remaining },
+                chunk,
+            );
+            ec.responses.push_back(response);
+        }
+        Ok(())
+    }
+
+    fn get_feature_report(&self, buf: &mut [u8]) -> Result<usize> {
+        let response = self.0.lock().unwrap().responses.pop_front().unwrap();
+        buf[0] = 0x00; // report id
+        buf[1..=response.len()].copy_from_slice(&response);
+        Ok(response.len() + 1)
+    }
+}
+
+fn device(transport: &FakeTransport) -> Device {
+    common::device(transport.clone())
+}
+
+fn bytes(len: usize) -> Vec<u8> {
+    (0..len).map(|i| i as u8).collect()
+}
+
+#[test]
+fn payload_is_split_across_reports() {
+    let transport = FakeTransport::default();
+    let payload = bytes(200);
+
+    let echo = device(&transport).send_payload(BULK_SET, &payload).unwrap();
+
+    let ec = transport.0.lock().unwrap();
+    assert_eq!(ec.headers, [(2, 80), (1, 80), (0, 40)]);
+    assert_eq!(ec.received, payload);
+    assert_eq!(echo, payload);
+}
+
+#[test]
+fn short_and_empty_payloads_take_a_single_report() {
+    let transport = FakeTransport::default();
+    let device = device(&transport);
+
+    assert_eq!(
+        device.send_payload(BULK_SET, &bytes(80)).unwrap(),
+        bytes(80)
+    );
+    assert!(device.send_payload(BULK_SET, &[]).unwrap().is_empty());
+    assert_eq!(transport.0.lock().unwrap().headers, [(0, 80), (0, 0)]);
+}
+
+#[test]
+fn multi_report_response_is_reassembled() {
+    let transport = FakeTransport::default();
+    transport.0.lock().unwrap().reply = bytes(170);
+
+    assert_eq!(
+        device(&transport).send_payload(BULK_GET, &[1]).unwrap(),
+        bytes(170)
+    );
+    assert!(transport.0.lock().unwrap().responses.is_empty());
+}
+
+#[test]
+fn response_reports_out_of_order_are_rejected() {
+    let transport = FakeTransport::default();
+    transport.0.lock().unwrap().reply = bytes(170);
+    transport.0.lock().unwrap().out_of_order = true;
+
+    assert!(matches!(
+        device(&transport).send_payload(BULK_GET, &[1]),
+        Err(Error::ResponseMismatch { .. })
+    ));
+}
+
+#[test]
+fn multi_report_response_is_recorded_and_replayed() {
+    let transport = FakeTransport::default();
+    transport.0.lock().unwrap().reply = bytes(170);
+    let path = std::env::temp_dir().join(format!("librazer-chunking-{}.jsonl", std::process::id()));
+
+    let recorded = replay::record(device(&transport), &path).unwrap();
+    let data = recorded.send_payload(BULK_GET, &bytes(100)).unwrap();
+
+    let replayed =
+        device(&FakeTransport::default()).map_transport(|_| ReplayTransport::open(&path).unwrap());
+    assert_eq!(replayed.send_payload(BULK_GET, &bytes(100)).unwrap(), data);
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn too_many_args_for_a_single_report_are_an_error() {
+    let transport = FakeTransport::default();
+    let device = device(&transport);
+
+    assert!(matches!(
+        command::send_command(&device, BULK_SET, &bytes(81)),
+        Err(Error::InvalidArgument(_))
+    ));
+    assert!(matches!(
+        command::custom_command(&device, BULK_SET, &bytes(81)),
+        Err(Error::InvalidArgument(_))
+    ));
+    assert!(transport.0.lock().unwrap().headers.is_empty());
+}
@@ -0,0 +1,129 @@
+mod common;
+
+use common::{FakeTransport, ARGS};
+use librazer::commands::*;
+use librazer::device::Device;
+use librazer::types::{
+    BatteryCare, Cluster, FanMode, FanZone, LightsAlwaysOn, LogoMode, MaxFanSpeedMode, PerfMode,
+};
+use librazer::{Error, Result};
+
+use std::fmt::Debug;
+
+/// Arguments of the report for `request`, and what `response` decodes to.
+fn vector<C: RazerCommand>(request: C::Request, args: &[u8], response: &[u8], decoded: C::Response)
+where
+    C::Response: Debug + PartialEq,
+{
+    assert_eq!(C::encode(&request).unwrap(), args, "{:04x}", C::COMMAND);
+    assert_eq!(C::decode(response).unwrap(), decoded, "{:04x}", C::COMMAND);
+}
+
+#[test]
+fn perf_mode_vectors() {
+    vector::<SetPerfMode>(
+        (FanZone::Zone2, PerfMode::Custom, FanMode::Manual),
+        &[1, 2, 4, 1],
+        &[1, 2, 4, 1],
+        (),
+    );
+    vector::<GetPerfMode>(
+        FanZone::Zone1,
+        &[0, 1, 0, 0],
+        &[0, 1, 4, 1],
+        (PerfMode::Custom, FanMode::Manual),
+    );
+    vector::<SetBoost>((Cluster::Gpu, 2), &[1, 2, 2], &[1, 2, 2], ());
+    vector::<GetBoost>(Cluster::Cpu, &[0, 1, 0], &[0, 1, 3], 3);
+}
+
+#[test]
+fn fan_vectors() {
+    vector::<SetFanRpm>((FanZone::Zone1, 3500), &[0, 1, 35], &[0, 1, 35], ());
+    vector::<GetFanRpm>(FanZone::Zone2, &[0, 2, 0], &[0, 2, 42], 4200);
+    vector::<GetFanActualRpm>(FanZone::Zone1, &[0, 1, 0], &[0, 1, 20], 2000);
+    vector::<SetMaxFanSpeedMode>(MaxFanSpeedMode::Enable, &[2], &[2], ());
+    vector::<GetMaxFanSpeedMode>((), &[0], &[0], MaxFanSpeedMode::Disable);
+}
+
+#[test]
+fn lighting_vectors() {
+    vector::<SetLogoPower>(true, &[1, 4, 1], &[1, 4, 1], ());
+    vector::<GetLogoPower>((), &[1, 4, 0], &[1, 4, 0], false);
+    vector::<SetLogoMode>(LogoMode::Breathing, &[1, 4, 2], &[1, 4, 2], ());
+    vector::<GetLogoMode>((), &[1, 4, 0], &[1, 4, 0], LogoMode::Static);
+    vector::<SetKeyboardBrightness>(0x80, &[1, 5, 0x80], &[1, 5, 0x80], ());
+    vector::<GetKeyboardBrightness>((), &[1, 5, 0], &[1, 5, 0xff], 0xff);
+    vector::<SetLightsAlwaysOn>(LightsAlwaysOn::Enable, &[3, 0], &[3, 0], ());
+    vector::<GetLightsAlwaysOn>((), &[0, 0], &[0, 0], LightsAlwaysOn::Disable);
+}
+
+#[test]
+fn battery_care_vectors() {
+    vector::<SetBatteryCare>(BatteryCare::Percent80, &[0xd0], &[0xd0], ());
+    vector::<GetBatteryCare>((), &[0], &[0x50], BatteryCare::Disable);
+}
+
+#[test]
+fn invalid_requests_and_responses() {
+    assert!(matches!(
+        SetFanRpm::encode(&(FanZone::Zone1, 5600)),
+        Err(Error::InvalidArgument(_))
+    ));
+    assert!(matches!(
+        SetLogoMode::encode(&LogoMode::Off),
+        Err(Error::InvalidArgument(_))
+    ));
+    assert!(matches!(
+        GetLogoPower::decode(&[1, 4, 7]),
+        Err(Error::InvalidValue { .. })
+    ));
+    assert!(matches!(
+        GetLogoMode::decode(&[1, 4, 1]),
+        Err(Error::InvalidValue { .. })
+    ));
+    assert!(GetPerfMode::decode(&[0, 1, 9, 0]).is_err());
+}
+
+/// Answers every report with a successful echo, altered by `tamper`.
+fn device(tamper: fn(&mut [u8])) -> Device {
+    common::device(FakeTransport::tampering(tamper))
+}
+
+fn is_mismatch(result: Result<impl Debug>) -> bool {
+    matches!(result, Err(Error::ResponseMismatch { .. }))
+}
+
+#[test]
+fn execute_checks_the_declared_validation() {
+    let echo = device(|_| ());
+    assert!(execute::<SetKeyboardBrightness>(&echo, &0x80).is_ok());
+    assert_eq!(execute::<GetFanRpm>(&echo, &FanZone::Zone2).unwrap(), 0);
+
+    // a setter whose value is not taken
+    let clamped = device(|report| report[ARGS + 2] = 0x40);
+    assert!(is_mismatch(execute::<SetKeyboardBrightness>(
+        &clamped, &0x80
+    )));
+    assert_eq!(
+        execute::<GetKeyboardBrightness>(&clamped, &()).unwrap(),
+        0x40
+    );
+
+    // a getter answering for another zone
+    let other_zone = device(|report| report[ARGS + 1] ^= 0x03);
+    assert!(is_mismatch(execute::<GetFanRpm>(
+        &other_zone,
+        &FanZone::Zone1
+    )));
+    assert!(execute::<GetMaxFanSpeedMode>(&other_zone, &()).is_ok());
+}
+
+#[test]
+fn execute_surfaces_decoding_errors() {
+    let device = device(|report| report[ARGS + 2] = 7);
+    assert!(matches!(
+        execute::<GetLogoPower>(&device, &()),
+        Err(Error::InvalidValue { .. })
+    ));
+}
@@ -0,0 +1,138 @@
+// shared by the tests talking to a `Device` through a fake EC, each uses a part of it
+#![allow(dead_code)]
+
+use librazer::descriptor::SUPPORTED;
+use librazer::device::Device;
+use librazer::policy::DevicePolicy;
+use librazer::transport::Transport;
+use librazer::{Packet, Result};
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+pub const BUSY: u8 = 0x01;
+pub const SUCCESSFUL: u8 = 0x02;
+pub const FAILURE: u8 = 0x03;
+pub const TIMEOUT: u8 = 0x04;
+pub const NOT_SUPPORTED: u8 = 0x05;
+
+// offsets in a report, without the report id, for altering what `Packet` only reads
+pub const STATUS: usize = 0;
+pub const ID: usize = 1;
+pub const REMAINING_PACKETS: usize = 2;
+pub const DATA_SIZE: usize = 5;
+pub const COMMAND_CLASS: usize = 6;
+pub const ARGS: usize = 8;
+pub const CRC: usize = 88;
+
+/// Recompute the crc of a report altered by hand, the status is not covered.
+pub fn seal(report: &mut [u8]) {
+    report[CRC] = report[REMAINING_PACKETS..CRC]
+        .iter()
+        .fold(0, |crc, b| crc ^ b);
+}
+
+/// No delays and a single attempt, tests add what they need.
+pub fn policy() -> DevicePolicy {
+    DevicePolicy::DEFAULT
+        .pre_send_delay(Duration::ZERO)
+        .read_delay(Duration::ZERO)
+        .max_attempts(1)
+        .backoff(Duration::ZERO, 1, Duration::ZERO)
+}
+
+/// The first supported model on top of `transport`, with `policy`.
+pub fn device(transport: impl Transport + 'static) -> Device {
+    Device::with_transport(SUPPORTED[0].clone(), transport).with_policy(policy())
+}
+
+/// Echoes every report with the next scripted status, successful once they run out.
+pub struct Ec {
+    pub statuses: VecDeque<u8>,
+    /// Alters the echo before its crc is computed, like a misbehaving EC.
+    pub tamper: fn(&mut [u8]),
+    /// Sent back instead of the echo, only the status is replaced.
+    pub response: Option<Vec<u8>>,
+    /// Every report, without the report id.
+    pub sent: Vec<Vec<u8>>,
+    /// Time between the last report and the read of its response.
+    pub read_after: Option<Duration>,
+    pending: Option<(Vec<u8>, Instant)>,
+}
+
+impl Default for Ec {
+    fn default() -> Ec {
+        Ec {
+            statuses: VecDeque::new(),
+            tamper: |_| (),
+            response: None,
+            sent: vec![],
+            read_after: None,
+            pending: None,
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct FakeTransport(Arc<Mutex<Ec>>);
+
+impl FakeTransport {
+    pub fn scripted(statuses: &[u8]) -> FakeTransport {
+        let transport = FakeTransport::default();
+        transport.ec().statuses = statuses.iter().copied().collect();
+        transport
+    }
+
+    pub fn tampering(tamper: fn(&mut [u8])) -> FakeTransport {
+        let transport = FakeTransport::default();
+        transport.ec().tamper = tamper;
+        transport
+    }
+
+    pub fn ec(&self) -> MutexGuard<'_, Ec> {
+        self.0.lock().unwrap()
+    }
+
+    pub fn sent(&self) -> Vec<Packet> {
+        self.ec()
+            .sent
+            .iter()
+            .map(|report| Packet::try_from(&report[..]).unwrap())
+            .collect()
+    }
+
+    pub fn reports(&self) -> usize {
+        self.ec().sent.len()
+    }
+}
+
+impl Transport for FakeTransport {
+    fn send_feature_report(&self, data: &[u8]) -> Result<()> {
+        let mut ec = self.ec();
+        // skip report id byte
+        let report = data[1..].to_vec();
+        let mut response = match &ec.response {
+            Some(response) => response.clone(),
+            None => {
+                let mut echo = report.clone();
+                (ec.tamper)(&mut echo);
+                seal(&mut echo);
+                echo
+            }
+        };
+        response[STATUS] = ec.statuses.pop_front().unwrap_or(SUCCESSFUL);
+        ec.sent.push(report);
+        ec.pending = Some((response, Instant::now()));
+        Ok(())
+    }
+
+    fn get_feature_report(&self, buf: &mut [u8]) -> Result<usize> {
+        let mut ec = self.ec();
+        let (response, sent) = ec.pending.take().unwrap();
+        ec.read_after = Some(sent.elapsed());
+        buf[0] = 0x00; // report id
+        buf[1..=response.len()].copy_from_slice(&response);
+        Ok(response.len() + 1)
+    }
+}
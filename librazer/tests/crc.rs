@@ -0,0 +1,58 @@
+mod common;
+
+use common::{FakeTransport, ARGS, CRC, DATA_SIZE, ID};
+use librazer::descriptor::SUPPORTED;
+use librazer::device::Device;
+use librazer::protocol::{Protocol, TransactionId};
+use librazer::{command, Error};
+
+const CAPTURE: &str = include_str!("../../data/wireshark_dump_raw.csv");
+
+/// Request of the given frame number in the capture, without the report id.
+fn captured(frame: &str) -> Vec<u8> {
+    let line = CAPTURE
+        .lines()
+        .find(|line| line.starts_with(&format!("\"{}\",", frame)))
+        .unwrap();
+    let data = line.split(',').nth(6).unwrap().trim_matches('"');
+    (0..data.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&data[i..i + 2], 16).unwrap())
+        .collect()
+}
+
+/// Device sending with the transaction id of the captured report.
+fn device(transport: &FakeTransport, request: &[u8]) -> Device {
+    let mut descriptor = SUPPORTED[0].clone();
+    descriptor.protocol = Protocol::DEFAULT.transaction_id(TransactionId::Fixed(request[ID]));
+    Device::with_transport(descriptor, transport.clone()).with_policy(common::policy())
+}
+
+fn args(request: &[u8]) -> &[u8] {
+    &request[ARGS..ARGS + request[DATA_SIZE] as usize]
+}
+
+#[test]
+fn report_matches_captured_crc() {
+    // 0x030b with 0x37 bytes of arguments, the capture carries crc 0xd0
+    let request = captured("925");
+    assert_eq!(request[CRC], 0xd0);
+
+    let transport = FakeTransport::default();
+    command::send_command(&device(&transport, &request), 0x030b, args(&request)).unwrap();
+    assert_eq!(transport.ec().sent, [request]);
+}
+
+#[test]
+fn flipped_byte_is_invalid_report() {
+    let request = captured("925");
+    let transport = FakeTransport::default();
+    let mut response = request.clone();
+    response[ARGS + 3] ^= 0x01;
+    transport.ec().response = Some(response);
+
+    assert!(matches!(
+        command::send_command(&device(&transport, &request), 0x030b, args(&request)),
+        Err(Error::InvalidReport { .. })
+    ));
+}
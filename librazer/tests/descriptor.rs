@@ -0,0 +1,139 @@
+use librazer::descriptor::{self, InitStep, SUPPORTED};
+use librazer::protocol::TransactionId;
+use librazer::quirks::Quirk;
+use librazer::Error;
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Write `content` to a fresh directory of the temp dir and return the path of the file.
+fn fixture(test: &str, file: &str, content: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("librazer-{}-{}", test, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(file);
+    std::fs::write(&path, content).unwrap();
+    path
+}
+
+const TOML: &str = r#"
+model_number_prefix = "RZ09-0509X"
+name = "Razer Blade 18 (2024)"
+pid = 0x02a0
+features = ["battery-care", "fan", "kbd-backlight", "lights-always-on", "perf"]
+init = [{ command = 0x0081, args = [0, 0, 0, 0] }, { command = 0x0086, expect = [1] }]
+timing = { read_delay_us = 5000 }
+protocol = { transaction_id = { fixed = 0x1f }, protocol_type = 0 }
+quirks = [{ quirk = "extra-delay", command = 0x0d82, delay_us = 5000 }]
+"#;
+
+#[test]
+fn toml_file_loads() {
+    let path = fixture("descriptor-toml", "blade-18.toml", TOML);
+    let descriptor = descriptor::load(&path).unwrap();
+
+    assert_eq!(descriptor.model_number_prefix, "RZ09-0509X");
+    assert_eq!(descriptor.name, "Razer Blade 18 (2024)");
+    assert_eq!(descriptor.pid, 0x02a0);
+    assert_eq!(descriptor.features.len(), 5);
+    assert_eq!(
+        descriptor.init,
+        [
+            InitStep::new(0x0081, &[0, 0, 0, 0]),
+            InitStep::new(0x0086, &[]).expect(&[1]),
+        ]
+    );
+    assert_eq!(descriptor.policy.read_delay, Duration::from_millis(5));
+    assert_eq!(
+        descriptor.protocol.transaction_id,
+        TransactionId::Fixed(0x1f)
+    );
+    assert!(descriptor.quirks.contains(&Quirk::ExtraDelay {
+        command: 0x0d82,
+        delay: Duration::from_millis(5),
+    }));
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn json_file_loads() {
+    let path = fixture(
+        "descriptor-json",
+        "blade-18.json",
+        r#"{
+            "model_number_prefix": "RZ09-0509X",
+            "name": "Razer Blade 18 (2024)",
+            "pid": 672,
+            "features": ["fan", "perf"]
+        }"#,
+    );
+    let descriptor = descriptor::load(&path).unwrap();
+
+    assert_eq!(descriptor.pid, 0x02a0);
+    assert_eq!(descriptor.features, ["fan", "perf"]);
+    assert!(descriptor.init.is_empty());
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn unknown_feature_is_rejected() {
+    let path = fixture(
+        "descriptor-feature",
+        "blade-18.toml",
+        &TOML.replace("\"fan\"", "\"turbo\""),
+    );
+    let Err(Error::InvalidData(message)) = descriptor::load(&path) else {
+        panic!("unknown feature was accepted");
+    };
+    assert!(message.contains("turbo"));
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn empty_model_number_prefix_is_rejected() {
+    let path = fixture(
+        "descriptor-prefix",
+        "blade-18.toml",
+        &TOML.replace("\"RZ09-0509X\"", "\"\""),
+    );
+    assert!(matches!(
+        descriptor::load(&path),
+        Err(Error::InvalidData(_))
+    ));
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn oversized_init_args_are_rejected() {
+    let args = vec!["0"; 81].join(", ");
+    let path = fixture(
+        "descriptor-init",
+        "blade-18.toml",
+        &TOML.replace("args = [0, 0, 0, 0]", &format!("args = [{}]", args)),
+    );
+    let Err(Error::InvalidData(message)) = descriptor::load(&path) else {
+        panic!("oversized init step was accepted");
+    };
+    assert!(message.contains("blade-18.toml") && message.contains("init step 0"));
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn file_takes_precedence_over_builtin() {
+    let path = fixture(
+        "descriptor-precedence",
+        "blade-16.toml",
+        &TOML
+            .replace("RZ09-0509X", "RZ09-0510S")
+            .replace("Razer Blade 18 (2024)", "Patched Blade 16"),
+    );
+
+    let supported = descriptor::supported_from(&[path.parent().unwrap().to_path_buf()]);
+    let matching: Vec<_> = supported
+        .iter()
+        .filter(|descriptor| descriptor.model_number_prefix == "RZ09-0510S")
+        .collect();
+    assert_eq!(matching.len(), 1);
+    assert_eq!(matching[0].name, "Patched Blade 16");
+    assert_eq!(supported.len(), SUPPORTED.len());
+    std::fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,38 @@
+use librazer::device::Device;
+use librazer::policy::DevicePolicy;
+use librazer::sim::{self, SimulatedBlade};
+use librazer::Error;
+
+use std::time::Duration;
+
+fn device(key: &str) -> Device {
+    let descriptor = sim::find("RZ09-0510S").unwrap();
+    Device::with_transport(descriptor.clone(), SimulatedBlade::new(descriptor))
+        .with_policy(DevicePolicy::DEFAULT.lock_timeout(Duration::from_millis(50)))
+        .with_lock(key)
+}
+
+#[test]
+fn contending_lock_times_out() {
+    let key = format!("test-contention-{}", std::process::id());
+    let holder = device(&key);
+    let waiter = device(&key);
+
+    let guard = holder.lock().unwrap();
+    let Err(Error::Locked { timeout, .. }) = waiter.lock() else {
+        panic!("second device got the lock while it was held");
+    };
+    assert_eq!(timeout, Duration::from_millis(50));
+
+    drop(guard);
+    assert!(waiter.lock().is_ok());
+}
+
+#[test]
+fn lock_is_reentrant_within_a_device() {
+    let key = format!("test-reentrant-{}", std::process::id());
+    let device = device(&key);
+
+    let _outer = device.lock().unwrap();
+    assert!(device.lock().is_ok());
+}
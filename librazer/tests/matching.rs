@@ -0,0 +1,105 @@
+use librazer::descriptor::SUPPORTED;
+use librazer::matching::{self, MatchKind, SystemInfo};
+
+use std::path::PathBuf;
+
+/// sysfs tree with the given DMI attributes, laid out like `/sys`.
+fn sysfs(name: &str, attributes: &[(&str, &str)]) -> PathBuf {
+    let root = std::env::temp_dir().join(format!("librazer-sysfs-{}-{}", name, std::process::id()));
+    let dmi = root.join("devices/virtual/dmi/id");
+    std::fs::create_dir_all(&dmi).unwrap();
+    for (attribute, value) in attributes {
+        std::fs::write(dmi.join(attribute), format!("{}\n", value)).unwrap();
+    }
+    root
+}
+
+fn find(
+    name: &str,
+    attributes: &[(&str, &str)],
+    pids: &[u16],
+) -> Option<(&'static str, MatchKind)> {
+    let root = sysfs(name, attributes);
+    let system = SystemInfo::from_sysfs(&root);
+    std::fs::remove_dir_all(root).unwrap();
+    matching::find(&system, pids, SUPPORTED)
+        .map(|found| (found.descriptor.model_number_prefix, found.kind))
+}
+
+#[test]
+fn full_sku_matches_model_number_prefix() {
+    assert_eq!(
+        find("sku", &[("product_sku", "RZ09-05288EB3")], &[]),
+        Some(("RZ09-05288", MatchKind::Sku))
+    );
+}
+
+#[test]
+fn wildcards() {
+    assert!(matching::matches_model("RZ09-0528?", "RZ09-05286EB3"));
+    assert!(matching::matches_model("rz09-*6", "RZ09-05286"));
+    assert!(!matching::matches_model("RZ09-0528?", "RZ09-0510S"));
+    assert!(!matching::matches_model("RZ09-0528?", "RZ09-0528"));
+}
+
+#[test]
+fn empty_sku_falls_back_to_product_name() {
+    let attributes = [
+        ("product_sku", ""),
+        ("product_name", "Blade 14 - RZ09-0482"),
+        ("board_name", "Blade"),
+    ];
+    assert_eq!(
+        find("product-name", &attributes, &[0x029d]),
+        Some(("RZ09-0482X", MatchKind::ProductName))
+    );
+}
+
+#[test]
+fn generic_product_name_is_only_a_guess() {
+    let attributes = [("product_sku", ""), ("product_name", "Blade")];
+    assert_eq!(
+        find("generic-name", &attributes, &[0x02c6]),
+        Some(("RZ09-05289", MatchKind::Pid))
+    );
+    assert_eq!(
+        find("razer-name", &[("product_name", "Razer")], &[0x029d]),
+        Some(("RZ09-0482X", MatchKind::Pid))
+    );
+    assert_eq!(
+        find(
+            "exact-name",
+            &[("product_name", "Razer Blade 16 (2024)")],
+            &[0x02b7]
+        ),
+        Some(("RZ09-0510S", MatchKind::ProductName))
+    );
+}
+
+#[test]
+fn board_name_fallback() {
+    let attributes = [
+        ("product_name", "To be filled by O.E.M."),
+        ("board_name", "RZ09-0421N"),
+    ];
+    assert_eq!(
+        find("board-name", &attributes, &[]),
+        Some(("RZ09-0421N", MatchKind::BoardName))
+    );
+}
+
+#[test]
+fn unknown_sku_is_guessed_by_pid() {
+    assert_eq!(
+        find("pid", &[("product_sku", "RZ09-0999X")], &[0x1234, 0x02b7]),
+        Some(("RZ09-0510S", MatchKind::Pid))
+    );
+}
+
+#[test]
+fn no_match_without_razer_pids() {
+    assert_eq!(
+        find("none", &[("product_sku", "20XW0055GE")], &[0x1234]),
+        None
+    );
+}
@@ -0,0 +1,45 @@
+use librazer::device::Device;
+use librazer::monitor::{self, DeviceEvent};
+use librazer::sim::{self, SimulatedBlade};
+use librazer::Error;
+
+use std::collections::BTreeSet;
+
+fn interfaces(list: &[(u16, &str)]) -> BTreeSet<(u16, String)> {
+    list.iter()
+        .map(|(pid, path)| (*pid, path.to_string()))
+        .collect()
+}
+
+#[test]
+fn unchanged_interfaces_have_no_events() {
+    let known = interfaces(&[(0x02b6, "/dev/hidraw2"), (0x02b6, "/dev/hidraw3")]);
+    assert!(monitor::changes(&known, &known.clone()).is_empty());
+}
+
+#[test]
+fn reset_interfaces_are_removed_then_added() {
+    // the EC came back from a reset on other hidraw nodes
+    let known = interfaces(&[(0x02b6, "/dev/hidraw2"), (0x02b6, "/dev/hidraw3")]);
+    let current = interfaces(&[(0x02b6, "/dev/hidraw3"), (0x02b6, "/dev/hidraw5")]);
+    assert_eq!(
+        monitor::changes(&known, &current),
+        [
+            DeviceEvent::Removed {
+                pid: 0x02b6,
+                path: "/dev/hidraw2".to_string()
+            },
+            DeviceEvent::Added {
+                pid: 0x02b6,
+                path: "/dev/hidraw5".to_string()
+            },
+        ]
+    );
+}
+
+#[test]
+fn device_without_interface_does_not_reconnect() {
+    let descriptor = sim::find("RZ09-0510S").unwrap();
+    let mut device = Device::with_transport(descriptor.clone(), SimulatedBlade::new(descriptor));
+    assert!(matches!(device.reconnect(), Err(Error::InvalidState(_))));
+}
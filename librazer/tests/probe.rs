@@ -0,0 +1,54 @@
+use librazer::descriptor::Descriptor;
+use librazer::device::Device;
+use librazer::feature;
+use librazer::probe::Support;
+use librazer::sim::{self, SimulatedBlade};
+
+/// Unknown model with every feature enabled, talking to a simulated Blade 14 (2023).
+fn unknown() -> (Device, Descriptor) {
+    let blade = sim::find("RZ09-0482X").unwrap();
+    let unknown = Descriptor {
+        model_number_prefix: "Unknown",
+        name: "Unknown",
+        features: feature::ALL_FEATURES,
+        ..blade.clone()
+    };
+    (
+        Device::with_transport(unknown.clone(), SimulatedBlade::new(blade)),
+        unknown,
+    )
+}
+
+#[test]
+fn missing_feature_is_not_supported() {
+    let (device, _) = unknown();
+    let capabilities = device.probe_capabilities();
+
+    let logo = capabilities
+        .features
+        .iter()
+        .find(|probe| probe.feature == "lid-logo")
+        .unwrap();
+    assert_eq!(logo.support, Support::NotSupported);
+    assert_eq!(logo.commands[0].command, 0x0380);
+}
+
+#[test]
+fn descriptor_keeps_the_supported_features() {
+    let (device, unknown) = unknown();
+    let descriptor = device.probe_capabilities().descriptor(&unknown);
+
+    let mut features = descriptor.features.to_vec();
+    features.sort();
+    assert_eq!(
+        features,
+        [
+            "battery-care",
+            "fan",
+            "kbd-backlight",
+            "lights-always-on",
+            "perf"
+        ]
+    );
+    assert_eq!(descriptor.name, "Unknown");
+}
@@ -0,0 +1,60 @@
+mod common;
+
+use common::{FakeTransport, BUSY, SUCCESSFUL};
+use librazer::command;
+use librazer::descriptor::SUPPORTED;
+use librazer::device::Device;
+use librazer::protocol::{Protocol, TransactionId};
+
+fn device(protocol: Protocol, statuses: &[u8]) -> (Device, FakeTransport) {
+    let transport = FakeTransport::scripted(statuses);
+    let mut descriptor = SUPPORTED[0].clone();
+    descriptor.protocol = protocol;
+    let device = Device::with_transport(descriptor, transport.clone())
+        .with_policy(common::policy().max_attempts(3));
+    (device, transport)
+}
+
+/// Transaction id and protocol type of every report sent.
+fn headers(transport: &FakeTransport) -> Vec<(u8, u8)> {
+    transport
+        .sent()
+        .iter()
+        .map(|report| (report.transaction_id(), report.protocol_type()))
+        .collect()
+}
+
+#[test]
+fn fixed_id_and_protocol_type_are_stamped_on_every_report() {
+    let protocol = Protocol::DEFAULT
+        .transaction_id(TransactionId::Fixed(0x1f))
+        .protocol_type(0x01);
+    let (device, transport) = device(protocol, &[]);
+    command::set_keyboard_brightness(&device, 10).unwrap();
+    command::get_keyboard_brightness(&device).unwrap();
+    assert_eq!(headers(&transport), [(0x1f, 0x01), (0x1f, 0x01)]);
+}
+
+#[test]
+fn incrementing_id_wraps_around() {
+    let protocol = Protocol::DEFAULT.transaction_id(TransactionId::Incrementing);
+    let (device, transport) = device(protocol, &[]);
+    for _ in 0..257 {
+        command::get_keyboard_brightness(&device).unwrap();
+    }
+    let ids: Vec<_> = headers(&transport).iter().map(|(id, _)| *id).collect();
+    assert_eq!(ids[..3], [0x01, 0x02, 0x03]);
+    assert_eq!(ids[253..], [0xfe, 0xff, 0x00, 0x01]);
+}
+
+#[test]
+fn retries_keep_the_id() {
+    let protocol = Protocol::DEFAULT.transaction_id(TransactionId::Incrementing);
+    let (device, transport) = device(protocol, &[SUCCESSFUL, BUSY, BUSY, SUCCESSFUL]);
+    command::set_keyboard_brightness(&device, 10).unwrap();
+    command::set_keyboard_brightness(&device, 20).unwrap();
+    assert_eq!(
+        headers(&transport),
+        [(0x01, 0x00), (0x02, 0x00), (0x02, 0x00), (0x02, 0x00)]
+    );
+}
@@ -0,0 +1,97 @@
+mod common;
+
+use common::{FakeTransport, ARGS, COMMAND_CLASS, CRC, ID, REMAINING_PACKETS};
+use librazer::descriptor::{Descriptor, SUPPORTED};
+use librazer::device::Device;
+use librazer::quirks::{self, HeaderField, Quirk};
+use librazer::{command, Error, Result};
+
+use std::time::Duration;
+
+fn device(quirks: &'static [Quirk], transport: &FakeTransport) -> Device {
+    let descriptor = Descriptor {
+        quirks,
+        ..SUPPORTED[0].clone()
+    };
+    Device::with_transport(descriptor, transport.clone()).with_policy(common::policy())
+}
+
+fn is_mismatch(result: Result<impl std::fmt::Debug>) -> bool {
+    matches!(result, Err(Error::ResponseMismatch { .. }))
+}
+
+#[test]
+fn default_quirks_tolerate_remaining_packets_of_bho_and_max_fan_speed() {
+    let transport = FakeTransport::tampering(|response| response[REMAINING_PACKETS + 1] = 1);
+    let device = device(quirks::DEFAULT, &transport);
+
+    assert!(command::send_command(&device, 0x0792, &[0x50]).is_ok());
+    assert!(command::send_command(&device, 0x078f, &[0]).is_ok());
+    assert!(is_mismatch(command::send_command(&device, 0x0d82, &[0, 1])));
+}
+
+#[test]
+fn tolerated_transaction_id_mismatch() {
+    const QUIRKS: &[Quirk] = &[Quirk::ToleratedMismatch {
+        command: 0x0d82,
+        field: HeaderField::TransactionId,
+    }];
+    let transport = FakeTransport::tampering(|response| response[ID] ^= 0xff);
+
+    assert!(is_mismatch(command::send_command(
+        &device(&[], &transport),
+        0x0d82,
+        &[0, 1]
+    )));
+    assert!(command::send_command(&device(QUIRKS, &transport), 0x0d82, &[0, 1]).is_ok());
+}
+
+#[test]
+fn extra_delay_before_reading_the_response() {
+    const QUIRKS: &[Quirk] = &[Quirk::ExtraDelay {
+        command: 0x0d82,
+        delay: Duration::from_millis(20),
+    }];
+    let transport = FakeTransport::default();
+    let device = device(QUIRKS, &transport);
+
+    command::send_command(&device, 0x0d82, &[0, 1]).unwrap();
+    assert!(transport.ec().read_after.unwrap() >= Duration::from_millis(20));
+
+    command::send_command(&device, 0x0d87, &[0, 1]).unwrap();
+    assert!(transport.ec().read_after.unwrap() < Duration::from_millis(20));
+}
+
+#[test]
+fn response_in_another_command_class() {
+    const QUIRKS: &[Quirk] = &[Quirk::ResponseClass {
+        command: 0x0d82,
+        class: 0x0f,
+    }];
+    let transport = FakeTransport::tampering(|response| response[COMMAND_CLASS] = 0x0f);
+
+    assert!(is_mismatch(command::send_command(
+        &device(&[], &transport),
+        0x0d82,
+        &[0, 1]
+    )));
+    assert!(command::send_command(&device(QUIRKS, &transport), 0x0d82, &[0, 1]).is_ok());
+}
+
+#[test]
+fn response_offset_drops_leading_bytes() {
+    const QUIRKS: &[Quirk] = &[Quirk::ResponseOffset {
+        command: 0x0d82,
+        offset: 2,
+    }];
+    let transport = FakeTransport::tampering(|response| {
+        response.copy_within(ARGS..CRC - 2, ARGS + 2);
+        response[ARGS..ARGS + 2].copy_from_slice(&[0xaa, 0xbb]);
+    });
+
+    let response = command::send_command(&device(&[], &transport), 0x0d82, &[0, 1, 2]).unwrap();
+    assert_eq!(&response.get_args()[..5], &[0xaa, 0xbb, 0, 1, 2]);
+
+    let response = command::send_command(&device(QUIRKS, &transport), 0x0d82, &[0, 1, 2]).unwrap();
+    assert_eq!(&response.get_args()[..5], &[0, 1, 2, 0, 0]);
+}
@@ -0,0 +1,65 @@
+mod common;
+
+use common::policy;
+use librazer::device::Device;
+use librazer::replay::{self, ReplayTransport};
+use librazer::{command, sim, Error};
+
+use std::path::{Path, PathBuf};
+
+const MODEL: &str = "RZ09-0510S";
+
+/// Recording of a simulated laptop reading the perf mode and setting the keyboard brightness.
+fn recording(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("librazer-{}-{}.jsonl", name, std::process::id()));
+    let device = replay::record(sim::open(MODEL).unwrap().with_policy(policy()), &path).unwrap();
+    command::get_perf_mode(&device).unwrap();
+    command::set_keyboard_brightness(&device, 0x20).unwrap();
+    path
+}
+
+fn replayed(path: &Path) -> Device {
+    let transport = ReplayTransport::open(path).unwrap();
+    Device::with_transport(sim::find(MODEL).unwrap(), transport).with_policy(policy())
+}
+
+#[test]
+fn same_sequence_replays() {
+    let path = recording("replay-same");
+    let device = replayed(&path);
+
+    assert!(command::get_perf_mode(&device).is_ok());
+    assert!(command::set_keyboard_brightness(&device, 0x20).is_ok());
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn different_request_diverges() {
+    let path = recording("replay-diverged");
+    let device = replayed(&path);
+
+    command::get_perf_mode(&device).unwrap();
+    // the perf mode getter took one exchange per fan zone
+    assert!(matches!(
+        command::set_keyboard_brightness(&device, 0x10),
+        Err(Error::ReplayDiverged { position: 2, .. })
+    ));
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn running_past_the_end_diverges() {
+    let path = recording("replay-end");
+    let device = replayed(&path);
+
+    command::get_perf_mode(&device).unwrap();
+    command::set_keyboard_brightness(&device, 0x20).unwrap();
+    let Err(Error::ReplayDiverged { position, message }) =
+        command::get_keyboard_brightness(&device)
+    else {
+        panic!("replay went past the end of the recording");
+    };
+    assert_eq!(position, 3);
+    assert!(message.starts_with("recording has ended"));
+    std::fs::remove_file(path).unwrap();
+}
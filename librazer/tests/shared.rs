@@ -0,0 +1,120 @@
+use librazer::shared::{Priority, SharedDevice};
+use librazer::{command, sim};
+
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+fn shared() -> SharedDevice {
+    SharedDevice::new(sim::open("RZ09-0510S").unwrap())
+}
+
+/// Occupy the worker until the returned sender is dropped.
+fn block(shared: &SharedDevice) -> (JoinHandle<()>, mpsc::Sender<()>) {
+    let (started, running) = mpsc::channel();
+    let (release, released) = mpsc::channel::<()>();
+    let handle = thread::spawn({
+        let shared = shared.clone();
+        move || {
+            shared.run(Priority::Interactive, move |_| {
+                started.send(()).unwrap();
+                let _ = released.recv();
+            })
+        }
+    });
+    running.recv().unwrap();
+    (handle, release)
+}
+
+/// Queue a transaction recording `label` from another thread, once it is in the queue.
+fn queue(
+    shared: &SharedDevice,
+    priority: Priority,
+    label: &'static str,
+    order: &Arc<Mutex<Vec<&'static str>>>,
+) -> JoinHandle<()> {
+    let pending = shared.pending();
+    let handle = thread::spawn({
+        let shared = shared.clone();
+        let order = order.clone();
+        move || shared.run(priority, move |_| order.lock().unwrap().push(label))
+    });
+    while shared.pending() == pending {
+        thread::yield_now();
+    }
+    handle
+}
+
+fn served(requests: &[(Priority, &'static str)]) -> Vec<&'static str> {
+    let shared = shared();
+    let order = Arc::new(Mutex::new(vec![]));
+    let (blocker, release) = block(&shared);
+    let handles: Vec<_> = requests
+        .iter()
+        .map(|&(priority, label)| queue(&shared, priority, label, &order))
+        .collect();
+
+    drop(release);
+    blocker.join().unwrap();
+    handles
+        .into_iter()
+        .for_each(|handle| handle.join().unwrap());
+    let order = order.lock().unwrap().clone();
+    order
+}
+
+#[test]
+fn interactive_is_served_before_background() {
+    let order = served(&[
+        (Priority::Background, "poll fan"),
+        (Priority::Interactive, "set perf mode"),
+        (Priority::Background, "poll perf mode"),
+    ]);
+    assert_eq!(order, ["set perf mode", "poll fan", "poll perf mode"]);
+}
+
+#[test]
+fn same_priority_is_served_in_order() {
+    let order = served(&[
+        (Priority::Interactive, "first"),
+        (Priority::Interactive, "second"),
+        (Priority::Interactive, "third"),
+    ]);
+    assert_eq!(order, ["first", "second", "third"]);
+}
+
+#[test]
+fn concurrent_callers_get_their_own_results() {
+    let shared = shared();
+    let handles: Vec<_> = (0..8_u8)
+        .map(|i| {
+            let shared = shared.clone();
+            thread::spawn(move || {
+                let brightness = shared.run(Priority::Background, move |device| {
+                    command::set_keyboard_brightness(device, i * 10)?;
+                    command::get_keyboard_brightness(device)
+                });
+                (i, brightness.unwrap())
+            })
+        })
+        .collect();
+
+    for handle in handles {
+        let (i, brightness) = handle.join().unwrap();
+        assert_eq!(brightness, i * 10);
+    }
+}
+
+#[test]
+fn panic_reaches_the_caller_and_the_worker_goes_on() {
+    let shared = shared();
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        shared.run(Priority::Interactive, |_| panic!("transaction failed"))
+    }));
+    let payload = result.unwrap_err();
+    assert_eq!(payload.downcast_ref::<&str>(), Some(&"transaction failed"));
+
+    assert!(shared
+        .run(Priority::Interactive, command::get_perf_mode)
+        .is_ok());
+}
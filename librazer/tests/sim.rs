@@ -0,0 +1,18 @@
+use librazer::{command, sim};
+
+#[test]
+fn getter_response_carries_the_simulated_args() {
+    let device = sim::open("RZ09-0510S").unwrap();
+    command::set_keyboard_brightness(&device, 0x42).unwrap();
+
+    let response = command::send_command(&device, 0x0383, &[0x01, 0x05]).unwrap();
+    assert_eq!(response.data(), [0x01, 0x05, 0x42]);
+    assert_eq!(response.data_size(), 3);
+}
+
+#[test]
+fn setter_response_echoes_the_report() {
+    let device = sim::open("RZ09-0510S").unwrap();
+    let response = command::send_command(&device, 0x0712, &[0x50]).unwrap();
+    assert_eq!(response.data(), [0x50]);
+}
@@ -0,0 +1,169 @@
+use librazer::sim;
+use librazer::state::{Change, DeviceState, FanSpeed, LightsMode, Outcome, PerfMode};
+use librazer::types::{self, BatteryCare, CpuBoost, FanMode, GpuBoost, LightsAlwaysOn, LogoMode};
+use librazer::Error;
+
+fn state(perf_mode: PerfMode, fan_speed: FanSpeed) -> DeviceState {
+    DeviceState {
+        perf_mode,
+        fan_speed,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn diff_orders_perf_mode_before_boosts_and_fan_mode_before_rpm() {
+    let current = state(PerfMode::Balanced, FanSpeed::Auto);
+    let target = state(
+        PerfMode::Custom(CpuBoost::Boost, GpuBoost::High),
+        FanSpeed::Manual(3000),
+    );
+
+    assert_eq!(
+        current.diff(&target),
+        [
+            Change::PerfMode(types::PerfMode::Custom),
+            Change::CpuBoost(CpuBoost::Boost),
+            Change::GpuBoost(GpuBoost::High),
+            Change::FanMode(FanMode::Manual),
+            Change::FanRpm(3000),
+        ]
+    );
+    assert!(target.diff(&target).is_empty());
+}
+
+#[test]
+fn diff_only_sends_changed_fields() {
+    let current = state(
+        PerfMode::Custom(CpuBoost::Boost, GpuBoost::High),
+        FanSpeed::Manual(3000),
+    );
+
+    let target = state(
+        PerfMode::Custom(CpuBoost::Boost, GpuBoost::Low),
+        FanSpeed::Manual(4000),
+    );
+    assert_eq!(
+        current.diff(&target),
+        [Change::GpuBoost(GpuBoost::Low), Change::FanRpm(4000)]
+    );
+
+    let target = DeviceState {
+        lights_mode: LightsMode {
+            keyboard_brightness: 0x80,
+            ..current.lights_mode
+        },
+        ..current
+    };
+    assert_eq!(current.diff(&target), [Change::KeyboardBrightness(0x80)]);
+}
+
+#[test]
+fn perf_mode_change_restores_manual_fan_speed() {
+    let current = state(PerfMode::Performance, FanSpeed::Manual(3000));
+    let target = state(PerfMode::Balanced, FanSpeed::Manual(3000));
+
+    assert_eq!(
+        current.diff(&target),
+        [
+            Change::PerfMode(types::PerfMode::Balanced),
+            Change::FanMode(FanMode::Manual),
+            Change::FanRpm(3000),
+        ]
+    );
+}
+
+#[test]
+fn apply_changes_on_simulated_laptop() {
+    let device = sim::open("RZ09-0510S").unwrap();
+    let current = DeviceState::read(&device).unwrap();
+    let target = DeviceState {
+        perf_mode: PerfMode::Custom(CpuBoost::High, GpuBoost::Medium),
+        lights_mode: LightsMode {
+            logo_mode: LogoMode::Breathing,
+            keyboard_brightness: 0x20,
+            always_on: LightsAlwaysOn::Enable,
+        },
+        battery_care: BatteryCare::Percent60,
+        fan_speed: FanSpeed::Manual(4500),
+    };
+
+    DeviceState::apply_changes(&device, &current.diff(&target)).unwrap();
+    assert_eq!(DeviceState::read(&device).unwrap(), target);
+
+    let sent = |command| device.stats().get(&command).map_or(0, |stats| stats.calls);
+    let before = (sent(0x0d02), sent(0x0712));
+    let dimmed = DeviceState {
+        lights_mode: LightsMode {
+            keyboard_brightness: 0x10,
+            ..target.lights_mode
+        },
+        ..target
+    };
+    DeviceState::apply_changes(&device, &target.diff(&dimmed)).unwrap();
+    assert_eq!((sent(0x0d02), sent(0x0712)), before);
+    assert_eq!(DeviceState::read(&device).unwrap(), dimmed);
+}
+
+#[test]
+fn transaction_applies_every_change() {
+    let device = sim::open("RZ09-0510S").unwrap();
+    let snapshot = DeviceState::read(&device).unwrap();
+    let target = state(PerfMode::Silent, FanSpeed::Manual(2500));
+
+    let report = snapshot.apply_transaction(&device, &target).unwrap();
+    assert!(report.failed().is_none() && report.rollback.is_empty());
+    assert!(report
+        .steps
+        .iter()
+        .all(|step| matches!(step.outcome, Outcome::Applied)));
+    assert_eq!(DeviceState::read(&device).unwrap(), target);
+}
+
+#[test]
+fn failed_transaction_is_rolled_back() {
+    let device = sim::open("RZ09-0510S").unwrap();
+    let snapshot = DeviceState::read(&device).unwrap();
+    // the rpm is out of range, everything before it has to be undone
+    let target = DeviceState {
+        battery_care: BatteryCare::Percent60,
+        ..state(
+            PerfMode::Custom(CpuBoost::Boost, GpuBoost::High),
+            FanSpeed::Manual(9000),
+        )
+    };
+
+    let Err(Error::Transaction(report)) = snapshot.apply_transaction(&device, &target) else {
+        panic!("transaction did not fail");
+    };
+    let outcomes: Vec<_> = report
+        .steps
+        .iter()
+        .map(|step| match step.outcome {
+            Outcome::Applied => "applied",
+            Outcome::Failed(_) => "failed",
+            Outcome::Skipped => "skipped",
+            Outcome::RolledBack => "rolled back",
+        })
+        .collect();
+    assert_eq!(
+        report.failed().map(|step| step.change),
+        Some(Change::FanRpm(9000))
+    );
+    assert_eq!(
+        &outcomes[..],
+        [
+            "rolled back",
+            "rolled back",
+            "rolled back",
+            "rolled back",
+            "failed",
+            "skipped",
+            "skipped",
+            "skipped",
+        ]
+    );
+    assert!(report.rolled_back());
+    assert!(report.to_string().ends_with(", rolled back"));
+    assert_eq!(DeviceState::read(&device).unwrap(), snapshot);
+}
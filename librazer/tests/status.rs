@@ -0,0 +1,191 @@
+mod common;
+
+use common::{FakeTransport, BUSY, FAILURE, NOT_SUPPORTED, SUCCESSFUL, TIMEOUT};
+use librazer::descriptor::InitStep;
+use librazer::device::Device;
+use librazer::policy::RetryPolicy;
+use librazer::{command, Error};
+
+use std::time::Duration;
+
+fn device(statuses: &[u8]) -> (Device, FakeTransport) {
+    let transport = FakeTransport::scripted(statuses);
+    let device = common::device(transport.clone()).with_policy(common::policy().max_attempts(3));
+    (device, transport)
+}
+
+#[test]
+fn successful_is_returned_immediately() {
+    let (device, transport) = device(&[SUCCESSFUL]);
+    command::set_keyboard_brightness(&device, 10).unwrap();
+    assert_eq!(transport.reports(), 1);
+}
+
+#[test]
+fn busy_and_timeout_are_retried() {
+    let (device, transport) = device(&[BUSY, TIMEOUT, SUCCESSFUL]);
+    command::set_keyboard_brightness(&device, 10).unwrap();
+    assert_eq!(transport.reports(), 3);
+}
+
+#[test]
+fn busy_is_reported_after_the_last_attempt() {
+    let (device, transport) = device(&[BUSY, BUSY, BUSY]);
+    let error = command::set_keyboard_brightness(&device, 10).unwrap_err();
+    assert!(
+        matches!(error, Error::Busy { command: 0x0303 }),
+        "{:?}",
+        error
+    );
+    assert_eq!(transport.reports(), 3);
+}
+
+#[test]
+fn timeout_is_reported_after_the_last_attempt() {
+    let (device, transport) = device(&[BUSY, BUSY, TIMEOUT]);
+    let error = command::set_keyboard_brightness(&device, 10).unwrap_err();
+    assert!(
+        matches!(error, Error::Timeout { command: 0x0303 }),
+        "{:?}",
+        error
+    );
+    assert_eq!(transport.reports(), 3);
+}
+
+#[test]
+fn failure_is_not_retried() {
+    let (device, transport) = device(&[FAILURE]);
+    let error = command::set_keyboard_brightness(&device, 10).unwrap_err();
+    assert!(
+        matches!(
+            error,
+            Error::CommandFailed {
+                command: 0x0303,
+                status: FAILURE
+            }
+        ),
+        "{:?}",
+        error
+    );
+    assert_eq!(transport.reports(), 1);
+}
+
+#[test]
+fn not_supported_is_not_retried() {
+    let (device, transport) = device(&[BUSY, NOT_SUPPORTED]);
+    let error = command::set_keyboard_brightness(&device, 10).unwrap_err();
+    assert!(
+        matches!(error, Error::NotSupported { command: 0x0303 }),
+        "{:?}",
+        error
+    );
+    assert_eq!(transport.reports(), 2);
+}
+
+#[test]
+fn unknown_status_is_a_failure() {
+    let (device, transport) = device(&[0x7f]);
+    let error = command::set_keyboard_brightness(&device, 10).unwrap_err();
+    assert!(
+        matches!(error, Error::CommandFailed { status: 0x7f, .. }),
+        "{:?}",
+        error
+    );
+    assert_eq!(transport.reports(), 1);
+}
+
+#[test]
+fn retry_delay_backs_off_up_to_the_limit() {
+    let policy = RetryPolicy {
+        max_attempts: 5,
+        initial_delay: Duration::from_millis(100),
+        multiplier: 2,
+        max_delay: Duration::from_millis(300),
+    };
+    let delays: Vec<_> = (0..4)
+        .map(|retry| policy.delay(retry).as_millis())
+        .collect();
+    assert_eq!(delays, [100, 200, 300, 300]);
+}
+
+#[test]
+fn default_retry_delay_is_fixed() {
+    let delays: Vec<_> = (0..4)
+        .map(|retry| RetryPolicy::DEFAULT.delay(retry).as_millis())
+        .collect();
+    assert_eq!(delays, [500, 500, 500, 500]);
+}
+
+#[test]
+fn stats_count_calls_retries_and_failures() {
+    let (device, _) = device(&[BUSY, SUCCESSFUL, FAILURE]);
+    command::set_keyboard_brightness(&device, 10).unwrap();
+    command::set_keyboard_brightness(&device, 20).unwrap_err();
+    let stats = device.stats()[&0x0303];
+    assert_eq!((stats.calls, stats.retries, stats.failures), (2, 1, 1));
+    assert!(stats.max_latency <= stats.total_latency);
+}
+
+// the fake echoes the report, so the third step gets back 01 instead of 02
+const INIT: &[InitStep] = &[
+    InitStep::new(0x0081, &[0, 0, 0, 0]),
+    InitStep::new(0x0086, &[0, 0, 0, 0]),
+    InitStep::new(0x0087, &[0x01]).expect(&[0x02]),
+];
+
+#[test]
+fn init_stops_at_unsupported_step() {
+    let (mut device, transport) = device(&[SUCCESSFUL, NOT_SUPPORTED]);
+    device.info.init = INIT;
+    let error = device.init().unwrap_err();
+    let Error::InitStep {
+        step: 1,
+        command: 0x0086,
+        source,
+    } = error
+    else {
+        panic!("{:?}", error);
+    };
+    assert!(matches!(*source, Error::NotSupported { command: 0x0086 }));
+    assert_eq!(transport.reports(), 2);
+}
+
+#[test]
+fn init_stops_at_unexpected_response() {
+    let (mut device, transport) = device(&[]);
+    device.info.init = INIT;
+    let error = device.init().unwrap_err();
+    let Error::InitStep {
+        step: 2,
+        command: 0x0087,
+        source,
+    } = error
+    else {
+        panic!("{:?}", error);
+    };
+    assert!(matches!(
+        *source,
+        Error::ResponseMismatch {
+            command: 0x0087,
+            ..
+        }
+    ));
+    assert_eq!(transport.reports(), 3);
+}
+
+#[test]
+fn init_rejects_oversized_args() {
+    static ARGS: [u8; 81] = [0; 81];
+    static OVERSIZED: &[InitStep] = &[InitStep::new(0x0081, &ARGS)];
+    let (mut device, transport) = device(&[]);
+    device.info.init = OVERSIZED;
+    let error = device.init().unwrap_err();
+    let Error::InitStep {
+        step: 0, source, ..
+    } = error
+    else {
+        panic!("{:?}", error);
+    };
+    assert!(matches!(*source, Error::InvalidArgument(_)));
+    assert_eq!(transport.reports(), 0);
+}
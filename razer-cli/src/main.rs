@@ -1,6 +1,7 @@
 use librazer::command;
 use librazer::device;
 use librazer::feature;
+use librazer::protocol::TransactionId;
 use librazer::types::{
     BatteryCare, CpuBoost, FanMode, FanZone, GpuBoost, LightsAlwaysOn, LogoMode, MaxFanSpeedMode,
     PerfMode,
@@ -70,7 +71,7 @@ macro_rules! impl_unary_cli {
                 match matches.subcommand() {
                     Some((ident, matches)) if ident == self.name() => {
                         let arg = matches.get_one::<$arg_type>("ARG").unwrap();
-                        $setter(device, *arg)
+                        Ok($setter(device, *arg)?)
                     }
                     Some(("info", _)) => Ok(println!("{}: {:?}", self.name(), $getter(device))),
                     _ => Ok(()),
@@ -177,7 +178,7 @@ impl Cli for CustomCommand {
                 let cmd = *matches.get_one::<u16>("COMMAND").unwrap();
                 let args: Vec<u8> = matches.get_many::<u8>("ARGS").unwrap().copied().collect();
                 println!("Running custom command: {:x?} {:?}", cmd, args);
-                command::custom_command(device, cmd, &args)
+                Ok(command::custom_command(device, cmd, &args)?)
             }
             _ => Ok(()),
         }
@@ -204,8 +205,8 @@ impl Cli for feature::Fan {
                 impl_unary_handle_cli! {<MaxFanSpeedMode>(matches, device, "max", "MAX", command::set_max_fan_speed_mode)}
 
                 match matches.subcommand() {
-                    Some(("auto", _)) => command::set_fan_mode(device, FanMode::Auto),
-                    Some(("manual", _)) => command::set_fan_mode(device, FanMode::Manual),
+                    Some(("auto", _)) => Ok(command::set_fan_mode(device, FanMode::Auto)?),
+                    Some(("manual", _)) => Ok(command::set_fan_mode(device, FanMode::Manual)?),
                     _ => Ok(()),
                 }
             }
@@ -284,24 +285,73 @@ impl Cli for feature::Perf {
     }
 }
 
-fn enumerate() -> Result<()> {
-    let (pid_list, model_number_prefix) = device::Device::enumerate()?;
+fn enumerate(matches: &clap::ArgMatches) -> Result<()> {
+    let enumeration = device::Device::enumerate()?;
 
-    println!("Model: {}", model_number_prefix);
+    if matches.get_flag("json") {
+        println!("{}", serde_json::to_string_pretty(&enumeration)?);
+        return Ok(());
+    }
+
+    let model = enumeration.model.as_deref().unwrap_or("Unknown");
+    println!("Model: {}", model);
+    let found = librazer::matching::find(&enumeration.system, &enumeration.pids(), librazer::descriptor::supported());
     println!(
         "Supported: {}",
-        librazer::descriptor::SUPPORTED
-            .iter()
-            .any(|supported| model_number_prefix == supported.model_number_prefix)
+        found.map_or("false".to_string(), |found| format!("{} (by {:?})", found.descriptor.model_number_prefix, found.kind))
     );
-    println!("PID: {:#06x?}", pid_list);
+    println!("PID: {:#06x?}", enumeration.pids());
+    println!();
+    println!("| Path | PID | Interface | Usage page | Usage | Serial | Product | Feature reports | Descriptor |");
+    println!("|---|---|---|---|---|---|---|---|---|");
+    for interface in &enumeration.interfaces {
+        println!(
+            "| {} | {:#06x} | {} | {:#06x} | {:#06x} | {} | {} | {} | {} |",
+            interface.path,
+            interface.product_id,
+            interface.interface_number,
+            interface.usage_page,
+            interface.usage,
+            interface.serial_number.as_deref().unwrap_or(""),
+            interface.product.as_deref().unwrap_or(""),
+            match &interface.probe_error {
+                Some(error) => format!("no ({})", error),
+                None => "yes".to_string(),
+            },
+            interface.descriptor.unwrap_or(""),
+        );
+    }
+    Ok(())
+}
+
+fn capture_import(matches: &clap::ArgMatches) -> Result<()> {
+    use librazer::capture;
+
+    let reports = capture::read_capture(matches.get_one::<String>("CAPTURE").unwrap())?;
+    let rows = match matches.get_one::<String>("annotations") {
+        Some(path) => capture::action_table(
+            &reports,
+            &capture::read_annotations(&std::fs::read_to_string(path)?)?,
+        )?,
+        // without annotations every report is listed with its capture time
+        None => reports
+            .into_iter()
+            .map(|report| capture::ActionRow {
+                action: format!("{:.6}", report.time),
+                command: report.command,
+                args: report.args,
+                crc_mismatch: report.crc_mismatch,
+            })
+            .collect(),
+    };
+    println!("{}", capture::to_markdown(&rows));
     Ok(())
 }
 
 fn taskkill() -> Result<()> {
     // Run nvidia-smi to get PIDs of GPU processes
     let output = procCommand::new("nvidia-smi")
-        .args(&["--query-compute-apps=pid", "--format=csv,noheader"])
+        .args(["--query-compute-apps=pid", "--format=csv,noheader"])
         .output()
         .expect("Failed to execute nvidia-smi");
 
@@ -372,31 +422,184 @@ fn gen_cli_features(feature_list: &[&str]) -> Vec<Box<dyn Cli>> {
         .collect()
 }
 
+// auto mode needs the device before clap runs, so look for its flags by hand
+fn auto_mode_arg(flag: &str) -> Option<String> {
+    let mut args = std::env::args().skip_while(|arg| arg != "auto");
+    while let Some(arg) = args.next() {
+        if arg == flag {
+            return args.next();
+        }
+        if let Some(value) = arg.strip_prefix(flag).and_then(|arg| arg.strip_prefix('=')) {
+            return Some(value.to_string());
+        }
+    }
+    None
+}
+
+fn policy_args(cmd: Command) -> Command {
+    cmd.arg(arg!(--"pre-send-delay" <US> "Sleep before every report, in microseconds").value_parser(clap::value_parser!(u64)))
+        .arg(arg!(--"read-delay" <US> "Sleep between a report and reading its response, in microseconds").value_parser(clap::value_parser!(u64)))
+        .arg(arg!(--"max-attempts" <N> "Attempts per report when the device is busy").value_parser(clap::value_parser!(usize)))
+        .arg(arg!(--"retry-delay" <MS> "Delay before the first retry, in milliseconds").value_parser(clap::value_parser!(u64)))
+        .arg(arg!(--"lock-timeout" <MS> "How long to wait for another process using the device, in milliseconds").value_parser(clap::value_parser!(u64)))
+        .arg(arg!(--stats "Print latency and retry counters of every command"))
+}
+
+fn policy_overrides(matches: &clap::ArgMatches) -> librazer::policy::PolicyOverrides {
+    librazer::policy::PolicyOverrides {
+        pre_send_delay_us: matches.get_one::<u64>("pre-send-delay").copied(),
+        read_delay_us: matches.get_one::<u64>("read-delay").copied(),
+        max_attempts: matches.get_one::<usize>("max-attempts").copied(),
+        retry_delay_ms: matches.get_one::<u64>("retry-delay").copied(),
+        lock_timeout_ms: matches.get_one::<u64>("lock-timeout").copied(),
+    }
+}
+
+// auto mode opens the device before clap runs, malformed values are left for clap to report
+fn auto_mode_policy_overrides() -> librazer::policy::PolicyOverrides {
+    let parse = |flag| auto_mode_arg(flag).and_then(|value| value.parse().ok());
+    librazer::policy::PolicyOverrides {
+        pre_send_delay_us: parse("--pre-send-delay"),
+        read_delay_us: parse("--read-delay"),
+        max_attempts: auto_mode_arg("--max-attempts").and_then(|value| value.parse().ok()),
+        retry_delay_ms: parse("--retry-delay"),
+        lock_timeout_ms: parse("--lock-timeout"),
+    }
+}
+
+fn print_stats(device: &device::Device) {
+    eprintln!("| Command | Name | Calls | Retries | Failures | Mean | Max |");
+    eprintln!("|---|---|---|---|---|---|---|");
+    for (command, stats) in device.stats() {
+        eprintln!(
+            "| {:#06x} | {} | {} | {} | {} | {:.2?} | {:.2?} |",
+            command,
+            librazer::registry::lookup(command).map_or("", |spec| spec.name),
+            stats.calls,
+            stats.retries,
+            stats.failures,
+            stats.mean_latency(),
+            stats.max_latency
+        );
+    }
+}
+
+fn run(device: device::Device, matches: &clap::ArgMatches, features: &Vec<Box<dyn Cli>>) -> Result<()> {
+    let result = handle(&device, matches, features);
+    if matches.get_flag("stats") {
+        print_stats(&device);
+    }
+    result
+}
+
+// narrow the features of manual mode down to the probed ones
+fn probe(
+    device: &mut device::Device,
+    matches: &clap::ArgMatches,
+    features: Vec<Box<dyn Cli>>,
+) -> Result<Vec<Box<dyn Cli>>> {
+    let capabilities = device.probe_capabilities();
+    for probe in &capabilities.features {
+        eprintln!("{}: {:?}", probe.feature, probe.support);
+    }
+    device.info = capabilities.descriptor(&device.info);
+
+    let (features, unsupported): (Vec<_>, Vec<_>) = features.into_iter().partition(|f| {
+        f.name() == CustomCommand.name() || device.info.features.contains(&f.name())
+    });
+    if let Some(cmd) = matches.subcommand_name() {
+        if let Some(f) = unsupported.iter().find(|f| f.cmd().is_some_and(|c| c.get_name() == cmd)) {
+            anyhow::bail!("{} is not supported by this device according to the probe", f.name());
+        }
+    }
+    Ok(features)
+}
+
+fn parse_transaction_id(value: &str) -> Result<TransactionId, String> {
+    match value {
+        "random" => Ok(TransactionId::Random),
+        "incrementing" => Ok(TransactionId::Incrementing),
+        id => clap_num::maybe_hex::<u8>(id).map(TransactionId::Fixed),
+    }
+}
+
+fn open(
+    descriptor: librazer::descriptor::Descriptor,
+    path: Option<&String>,
+    serial: Option<&String>,
+) -> Result<device::Device> {
+    Ok(match (path, serial) {
+        (Some(path), _) => device::Device::open_path(descriptor, path)?,
+        (_, Some(serial)) => device::Device::open_serial(descriptor, serial)?,
+        _ => device::Device::new(descriptor)?,
+    })
+}
+
+fn record(device: device::Device, path: Option<&String>) -> Result<device::Device> {
+    match path {
+        Some(path) => Ok(librazer::replay::record(device, path)?),
+        None => Ok(device),
+    }
+}
+
 fn main() -> Result<()> {
     env_logger::init();
     
     let info_cmd = clap::Command::new("info").about("Get device info");
+    let simulate_flag = arg!(--simulate <MODEL> "Run against a simulated laptop of the given model, e.g. RZ09-0510S");
+    let record_flag = arg!(--record <FILE> "Record every HID transaction of the session to a file");
+    let path_flag = arg!(--path <PATH> "Open the HID interface at this path, see `enumerate`").conflicts_with_all(["simulate", "serial"]);
+    let serial_flag = arg!(--serial <SERIAL> "Open the HID interface with this serial number").conflicts_with("simulate");
     let auto_cmd = clap::Command::new("auto")
         .about("Automatically detect supported Razer device and enable device specific features")
+        .arg(simulate_flag.clone())
+        .arg(record_flag.clone())
+        .arg(path_flag.clone())
+        .arg(serial_flag.clone())
         .subcommand(info_cmd.clone())
         .subcommand_required(true);
 
-    let manual_cmd =clap::Command::new("manual").about("Manually specify PID of the Razer device and enable all features (many might not work)")
+    let manual_cmd =clap::Command::new("manual").about("Manually specify PID of the Razer device and enable all features (many might not work, see --probe)")
             .arg(
                 arg!(-p --pid <PID> "PID of the Razer device to use")
-                .required(true)
+                .required_unless_present_any(["path", "simulate", "replay"])
+                .conflicts_with("path")
                 .value_parser(clap_num::maybe_hex::<u16>)
             )
+            .arg(simulate_flag.conflicts_with("replay"))
+            .arg(record_flag)
+            .arg(path_flag.conflicts_with("replay"))
+            .arg(serial_flag.conflicts_with("replay"))
+            .arg(arg!(--replay <FILE> "Replay a recorded session instead of talking to the hardware"))
+            .arg(arg!(--probe "Enable only the features the device answers the getters of"))
+            .arg(arg!(--"transaction-id" <ID> "Transaction id of the reports: random, incrementing or a fixed id, e.g. 0x1f").value_parser(parse_transaction_id))
             .arg_required_else_help(true)
             .subcommand(info_cmd)
             .subcommand_required(true);
 
     // TODO: find a better way to detect auto mode in advance
     let is_auto_mode = std::env::args_os().nth(1) == Some("auto".into());
-    let device = match is_auto_mode {
-        true => Some(device::Device::detect()?),
+    let device = match (is_auto_mode, auto_mode_arg("--simulate")) {
+        (true, Some(model)) => {
+            // the overrides have to be in place before the init sequence is sent
+            let mut descriptor = librazer::sim::find(&model)?;
+            descriptor.policy = auto_mode_policy_overrides().apply(descriptor.policy);
+            Some(librazer::sim::open_descriptor(descriptor)?)
+        }
+        (true, None) => {
+            let mut descriptor = device::Device::detect_descriptor()?;
+            descriptor.policy = auto_mode_policy_overrides().apply(descriptor.policy);
+            Some(open(
+                descriptor,
+                auto_mode_arg("--path").as_ref(),
+                auto_mode_arg("--serial").as_ref(),
+            )?)
+        }
         _ => None,
     };
+    let device = device
+        .map(|device| record(device, auto_mode_arg("--record").as_ref()))
+        .transpose()?;
     let feature_list = match device {
         Some(ref device) => device.info.features,
         _ => feature::ALL_FEATURES,
@@ -408,32 +611,94 @@ fn main() -> Result<()> {
     let cmd = clap::command!()
         .color(clap::ColorChoice::Always)
         .subcommand_required(true)
-        .subcommand(update_cmd(auto_cmd, &cli_features))
-        .subcommand(update_cmd(manual_cmd, &cli_features))
-        .subcommand(clap::Command::new("enumerate").about("List discovered Razer devices"))
-        .subcommand(clap::Command::new("taskkill").about("Terminate all processes using dGPU"));
+        .subcommand(update_cmd(policy_args(auto_cmd), &cli_features))
+        .subcommand(update_cmd(policy_args(manual_cmd), &cli_features))
+        .subcommand(
+            clap::Command::new("enumerate")
+                .about("List discovered Razer devices")
+                .arg(arg!(--json "Print the HID interfaces as JSON")),
+        )
+        .subcommand(clap::Command::new("taskkill").about("Terminate all processes using dGPU"))
+        .subcommand(
+            clap::Command::new("capture")
+                .about("Tools for reverse engineering USB captures")
+                .subcommand(
+                    clap::Command::new("import")
+                        .about("Print the action to command table of a Wireshark CSV export, pcap or pcapng capture")
+                        .arg(arg!(<CAPTURE> "Capture file"))
+                        .arg(arg!(-a --annotations <FILE> "Actions performed while capturing, one `<second> \"<action>\"` per line")),
+                )
+                .subcommand_required(true),
+        );
 
     let matches = cmd.get_matches();
 
     match matches.subcommand() {
-        Some(("enumerate", _)) => {
-            enumerate()?;
+        Some(("enumerate", submatches)) => {
+            enumerate(submatches)?;
         }
         Some(("taskkill", _)) => {
             taskkill()?;
         }
+        Some(("capture", submatches)) => {
+            if let Some(("import", submatches)) = submatches.subcommand() {
+                capture_import(submatches)?;
+            }
+        }
         Some(("auto", submatches)) => {
-            handle(&device.unwrap(), submatches, &cli_features)?;
+            run(device.unwrap(), submatches, &cli_features)?;
         }
         Some(("manual", submatches)) => {
-            let device = device::Device::new(librazer::descriptor::Descriptor {
+            let descriptor = librazer::descriptor::Descriptor {
                 model_number_prefix: "Unknown",
                 name: "Unknown",
-                pid: *submatches.get_one::<u16>("pid").unwrap(),
+                pid: match (
+                    submatches.get_one::<u16>("pid"),
+                    submatches.get_one::<String>("path"),
+                    submatches.get_one::<String>("simulate"),
+                ) {
+                    (Some(pid), _, _) => *pid,
+                    (_, Some(path), _) => device::Device::razer_pid_of_path(path)?,
+                    (_, _, Some(model)) => librazer::sim::find(model)?.pid,
+                    // a replay doesn't talk to any device
+                    _ => 0,
+                },
                 features: feature::ALL_FEATURES,
-                init_cmds : &[]
-            })?;
-            handle(&device, submatches, &cli_features)?;
+                init: &[],
+                policy: policy_overrides(submatches).apply(librazer::policy::DevicePolicy::DEFAULT),
+                protocol: librazer::protocol::Protocol::DEFAULT.transaction_id(
+                    submatches
+                        .get_one::<TransactionId>("transaction-id")
+                        .copied()
+                        .unwrap_or(TransactionId::Random),
+                ),
+                quirks: librazer::quirks::DEFAULT,
+            };
+            let device = match (
+                submatches.get_one::<String>("simulate"),
+                submatches.get_one::<String>("replay"),
+            ) {
+                (Some(model), _) => device::Device::with_transport(
+                    descriptor,
+                    librazer::sim::SimulatedBlade::new(librazer::sim::find(model)?),
+                ),
+                (_, Some(path)) => device::Device::with_transport(
+                    descriptor,
+                    librazer::replay::ReplayTransport::open(path)?,
+                ),
+                _ => open(
+                    descriptor,
+                    submatches.get_one::<String>("path"),
+                    submatches.get_one::<String>("serial"),
+                )?,
+            };
+            let mut device = record(device, submatches.get_one::<String>("record"))?;
+            let cli_features = if submatches.get_flag("probe") {
+                probe(&mut device, submatches, cli_features)?
+            } else {
+                cli_features
+            };
+            run(device, submatches, &cli_features)?;
         }
         Some((cmd, _)) => unimplemented!("Subcommand not implemented: {}", cmd),
         None => unreachable!(),
@@ -4,7 +4,9 @@ use serde::{Deserialize, Serialize};
 use strum::IntoEnumIterator;
 use anyhow::Error;
 
-use librazer::types::{BatteryCare, CpuBoost, GpuBoost, LightsAlwaysOn, LogoMode, FanMode};
+use librazer::types::{BatteryCare, CpuBoost, GpuBoost, LightsAlwaysOn, LogoMode};
+use librazer::policy::PolicyOverrides;
+use librazer::state::{DeviceState, FanSpeed, LightsMode, PerfMode};
 use librazer::{command, device};
 
 use tao::event_loop::{ControlFlow, EventLoopBuilder};
@@ -31,150 +33,32 @@ use windows::Win32::System::Power::{GetSystemPowerStatus, SYSTEM_POWER_STATUS};
 
 const PKG_NAME: &str = env!("CARGO_PKG_NAME");
 
-#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
-enum FanSpeed {
-    Auto,
-    Manual(u16),
-}
-
-#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
-enum PerfMode {
-    Battery,
-    Silent,
-    Balanced,
-    Performance,
-    Hyperboost,
-    Custom(CpuBoost, GpuBoost),
-}
-
-#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
-struct LightsMode {
-    logo_mode: LogoMode,
-    keyboard_brightness: u8,
-    always_on: LightsAlwaysOn,
-}
-
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 struct FanRpm {
     fan1: u16,
     fan2: u16,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
-struct DeviceState {
-    perf_mode: PerfMode,
-    lights_mode: LightsMode,
-    battery_care: BatteryCare,
-    fan_speed : FanSpeed,
-}
-
 type Result<T> = std::result::Result<T, Error>;
 
-impl DeviceState {
-    fn read(device: &device::Device) -> Result<Self> {
-        let perf_mode = match command::get_perf_mode(device)? {
-            (librazer::types::PerfMode::Battery, _) => PerfMode::Battery,
-            (librazer::types::PerfMode::Silent, _) => PerfMode::Silent,
-            (librazer::types::PerfMode::Balanced, _) => PerfMode::Balanced,
-            (librazer::types::PerfMode::Performance, _) => PerfMode::Performance,
-            (librazer::types::PerfMode::Hyperboost, _) => PerfMode::Hyperboost,
-            (librazer::types::PerfMode::Custom, _) => {
-                let cpu_boost = command::get_cpu_boost(device)?;
-                let gpu_boost = command::get_gpu_boost(device)?;
-                PerfMode::Custom(cpu_boost, gpu_boost)
-            }
-        };
-
-        let fan_speed = match command::get_perf_mode(device)? {
-            (_,FanMode::Auto) => FanSpeed::Auto,
-            (_,FanMode::Manual) => {
-                let rpm = command::get_fan_rpm(device, librazer::types::FanZone::Zone1)?;
-                FanSpeed::Manual(rpm)
-            }
-        };
-        let lights_mode = LightsMode {
-            logo_mode: command::get_logo_mode(device)?,
-            keyboard_brightness: command::get_keyboard_brightness(device)?,
-            always_on: command::get_lights_always_on(device)?,
-        };
-
-        let battery_care = command::get_battery_care(device)?;
-
-        Ok(Self {
-            perf_mode,            
-            lights_mode,
-            battery_care,
-            fan_speed
-        })
-    }
-
-    fn apply(&self, device: &device::Device) -> Result<()> {
-        match self.perf_mode {
-            PerfMode::Battery => command::set_perf_mode(device, librazer::types::PerfMode::Battery),
-            PerfMode::Silent => command::set_perf_mode(device, librazer::types::PerfMode::Silent),
-            PerfMode::Balanced => command::set_perf_mode(device, librazer::types::PerfMode::Balanced),
-            PerfMode::Performance => command::set_perf_mode(device, librazer::types::PerfMode::Performance),
-            PerfMode::Hyperboost => command::set_perf_mode(device, librazer::types::PerfMode::Hyperboost),
-            PerfMode::Custom(cpu_boost, gpu_boost) => {
-                command::set_perf_mode(device, librazer::types::PerfMode::Custom)?;
-                command::set_cpu_boost(device, cpu_boost)?;
-                command::set_gpu_boost(device, gpu_boost)
-            }
-        }?;
-
-        match self.fan_speed {
-            FanSpeed::Auto => command::set_fan_mode(device, librazer::types::FanMode::Auto),
-            FanSpeed::Manual(rpm) => {
-                command::set_fan_mode(device, librazer::types::FanMode::Manual)?;
-                command::set_fan_rpm(device, rpm, false)
-            }
-        }?;
-
-        match self.lights_mode.logo_mode {
-            LogoMode::Static => command::set_logo_mode(device, LogoMode::Static),
-            LogoMode::Breathing => command::set_logo_mode(device, LogoMode::Breathing),
-            LogoMode::Off => command::set_logo_mode(device, LogoMode::Off),
-        }?;
-
-        command::set_keyboard_brightness(device, self.lights_mode.keyboard_brightness)?;
-        command::set_lights_always_on(device, self.lights_mode.always_on)?;
-        command::set_battery_care(device, self.battery_care)
-    }
-
-    fn perf_delta(
-        &self,
-        cpu_boost: Option<CpuBoost>,
-        gpu_boost: Option<GpuBoost>,
-    ) -> Self {
-        DeviceState {
-            perf_mode: if let PerfMode::Custom(cb, gb) = self.perf_mode {
-                PerfMode::Custom(
-                    cpu_boost.unwrap_or(cb),
-                    gpu_boost.unwrap_or(gb)
-                )
-            } else {
-                PerfMode::Custom(
-                    cpu_boost.unwrap_or(CpuBoost::Boost),
-                    gpu_boost.unwrap_or(GpuBoost::High)
-                )
-            },
-            ..*self
-        }
-    }
-}
-
-impl Default for DeviceState {
-    fn default() -> Self {
-        Self {
-            perf_mode: PerfMode::Performance,
-            lights_mode: LightsMode {
-                logo_mode: LogoMode::Off,
-                keyboard_brightness: 0,
-                always_on: LightsAlwaysOn::Disable,
-            },
-            battery_care: BatteryCare::Percent80,
-            fan_speed : FanSpeed::Auto,
-        }
+fn perf_delta(
+    state: &DeviceState,
+    cpu_boost: Option<CpuBoost>,
+    gpu_boost: Option<GpuBoost>,
+) -> DeviceState {
+    DeviceState {
+        perf_mode: if let PerfMode::Custom(cb, gb) = state.perf_mode {
+            PerfMode::Custom(
+                cpu_boost.unwrap_or(cb),
+                gpu_boost.unwrap_or(gb)
+            )
+        } else {
+            PerfMode::Custom(
+                cpu_boost.unwrap_or(CpuBoost::Boost),
+                gpu_boost.unwrap_or(GpuBoost::High)
+            )
+        },
+        ..*state
     }
 }
 
@@ -184,20 +68,45 @@ trait DeviceStateDelta<T> {
 
 impl DeviceStateDelta<CpuBoost> for DeviceState {
     fn delta(&self, cpu_boost: CpuBoost) -> Self {
-        self.perf_delta(Some(cpu_boost), None)
+        perf_delta(self, Some(cpu_boost), None)
     }
 }
 
 impl DeviceStateDelta<GpuBoost> for DeviceState {
     fn delta(&self, gpu_boost: GpuBoost) -> Self {
-        self.perf_delta(None, Some(gpu_boost))
+        perf_delta(self, None, Some(gpu_boost))
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+// which HID interface to open, e.g. `device = { serial = "..." }`, see `razer-cli enumerate`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+struct DeviceSelection {
+    path: Option<String>,
+    serial: Option<String>,
+}
+
+impl DeviceSelection {
+    // the overrides have to be in place before the init sequence is sent
+    fn open(&self, overrides: &PolicyOverrides) -> librazer::Result<device::Device> {
+        let mut descriptor = device::Device::detect_descriptor()?;
+        descriptor.policy = overrides.apply(descriptor.policy);
+        match (&self.path, &self.serial) {
+            (Some(path), _) => device::Device::open_path(descriptor, path),
+            (_, Some(serial)) => device::Device::open_serial(descriptor, serial),
+            _ => device::Device::new(descriptor),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 struct ConfigState {
     ac_state: DeviceState,
     battery_state: DeviceState,
+    // timing overrides, e.g. `device_policy = { read_delay_us = 5000 }`
+    #[serde(default)]
+    device_policy: PolicyOverrides,
+    #[serde(default)]
+    device: DeviceSelection,
 }
 
 impl Default for ConfigState {
@@ -208,6 +117,8 @@ impl Default for ConfigState {
                     perf_mode : PerfMode::Battery,
                     ..Default::default()
                 },
+            device_policy: PolicyOverrides::default(),
+            device: DeviceSelection::default(),
         }
     }
 }
@@ -220,16 +131,18 @@ struct ProgramState {
     event_handlers: std::collections::HashMap<String, DeviceState>,
     menu: Menu,
     fan_actual : FanRpm,
-    ac_power : bool
+    ac_power : bool,
+    device_policy: PolicyOverrides,
+    device: DeviceSelection,
 }
 
 impl ProgramState {
     fn new(device_state: DeviceState, fan_last : FanRpm) -> Result<Self> {
         let (menu, event_handlers) = Self::create_menu_and_handlers(&device_state)?;
-        let fan_actual = fan_last.clone();
+        let fan_actual = fan_last;
         let ac_power = true;
-        let ac_state = device_state.clone();
-        let battery_state = device_state.clone();
+        let ac_state = device_state;
+        let battery_state = device_state;
         Ok(Self {
             device_state,
             ac_state,
@@ -237,7 +150,9 @@ impl ProgramState {
             event_handlers,
             menu,
             fan_actual,
-            ac_power
+            ac_power,
+            device_policy: PolicyOverrides::default(),
+            device: DeviceSelection::default(),
         })
     }
 
@@ -688,16 +603,17 @@ impl ProgramState {
         new_device_state: DeviceState,
         device: &device::Device
     ) -> Result<()> {
-        self.device_state = new_device_state.clone();
-        self.device_state.apply(device)?;
+        let report = self.device_state.apply_transaction(device, &new_device_state)?;
+        log::info!("{}", report);
+        self.device_state = new_device_state;
         (self.menu, self.event_handlers) = Self::create_menu_and_handlers(&self.device_state)?;
         self.fan_actual = get_fan_rpm(device)?;
         if self.ac_power {
-            self.ac_state = self.device_state.clone()
+            self.ac_state = self.device_state
         } else {
-            self.battery_state = self.device_state.clone()
+            self.battery_state = self.device_state
         }
-        confy::store(PKG_NAME, None, &ConfigState {ac_state : self.ac_state,battery_state :  self.battery_state})?;
+        confy::store(PKG_NAME, None, ConfigState {ac_state : self.ac_state,battery_state :  self.battery_state, device_policy: self.device_policy, device: self.device.clone()})?;
         tray_icon.set_icon(Some(self.icon()))?;
         tray_icon.set_tooltip(Some(self.tooltip()?))?;
         tray_icon.set_menu(Some(Box::new(self.menu.clone())));
@@ -769,7 +685,7 @@ fn gpu_taskkill() -> Result<()> {
 
     const CREATE_NO_WINDOW: u32 = 0x08000000;
     let output = procCommand::new("nvidia-smi")
-        .args(&["--query-compute-apps=name,pid", "--format=csv,noheader"])
+        .args(["--query-compute-apps=name,pid", "--format=csv,noheader"])
         .creation_flags(CREATE_NO_WINDOW)
         .output()
         .expect("Failed to execute nvidia-smi");
@@ -831,7 +747,7 @@ fn gpu_taskkill() -> Result<()> {
 fn gpu_taskkill() -> Result<()> {
     // dGPU process termination for Linux
     let output = procCommand::new("nvidia-smi")
-        .args(&["--query-compute-apps=name,pid", "--format=csv,noheader"])
+        .args(["--query-compute-apps=name,pid", "--format=csv,noheader"])
         .output();
     
     if output.is_err() {
@@ -907,17 +823,34 @@ fn init(tray_icon: &mut tray_icon::TrayIcon, device: &device::Device) -> Result<
     );
     let config: ConfigState = confy::load(PKG_NAME, None).unwrap_or_default();
     let fan_actual = get_fan_rpm(device)?;
-    let mut state = ProgramState::new(config.ac_state, fan_actual)?;
+    let mut state = ProgramState::new(DeviceState::read(device)?, fan_actual)?;
     state.ac_power = get_power_state()?;
-    state.ac_state = config.ac_state.clone();
-    state.battery_state = config.battery_state.clone();
-    if state.ac_power == false {
-        state.device_state = state.battery_state.clone()
-    }
-    state.update(tray_icon, state.device_state, device)?;
+    state.ac_state = config.ac_state;
+    state.battery_state = config.battery_state;
+    state.device_policy = config.device_policy;
+    state.device = config.device;
+    let new_device_state = match state.ac_power {
+        true => state.ac_state,
+        false => state.battery_state,
+    };
+    state.update(tray_icon, new_device_state, device)?;
     Ok(state)
 }
 
+// `razer-tray --simulate RZ09-0510S` runs against a simulated laptop
+fn simulate_arg() -> Option<String> {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--simulate" {
+            return args.next();
+        }
+        if let Some(model) = arg.strip_prefix("--simulate=") {
+            return Some(model.to_string());
+        }
+    }
+    None
+}
+
 #[cfg(target_os = "windows")]
 fn efficiency_mode() {
     unsafe {
@@ -959,13 +892,19 @@ fn main() -> Result<()> {
     init_logging_to_file()?;
     log::info!("{0} starting {1} {0}", "==".repeat(20), PKG_NAME);
 
-    let device = match device::Device::detect() {
+    let config: ConfigState = confy::load(PKG_NAME, None).unwrap_or_default();
+    let mut device = match simulate_arg().map_or_else(|| config.device.open(&config.device_policy), |model| {
+        let mut descriptor = librazer::sim::find(&model)?;
+        descriptor.policy = config.device_policy.apply(descriptor.policy);
+        librazer::sim::open_descriptor(descriptor)
+    }) {
         Ok(d) => {
             log::info!(
                 "detected device: {} (0x{:04X})",
                 d.info().name,
                 d.info().pid
             );
+            log::info!("device policy: {:?}", d.policy());
             d
         }
         Err(e) => {
@@ -974,7 +913,7 @@ fn main() -> Result<()> {
                 .set_type(native_dialog::MessageType::Error)
                 .set_text(format!("{:?}", e).as_str())
                 .show_alert()?;
-            return Err(e);
+            return Err(e.into());
         }
     };
 
@@ -988,15 +927,53 @@ fn main() -> Result<()> {
 
     let mut last_device_state_check_timestamp = std::time::Instant::now();
 
-    // loop through the default start up sequence to initialise the device.
-    for element in device.info().init_cmds {
-        command::send_command(&device, *element, &[0,0,0,0])?;
-    }
+    // when the device is gone, reconnect at the instant, backing off by the duration
+    let mut recovery: Option<(std::time::Instant, std::time::Duration)> = None;
+    let monitor = librazer::monitor::Monitor::new()
+        .inspect_err(|e| log::warn!("device monitor unavailable: {:?}", e))
+        .ok();
 
     event_loop.run(move |_, _, control_flow| {
         let now = std::time::Instant::now();
         *control_flow = ControlFlow::WaitUntil(now + std::time::Duration::from_millis(1000));
 
+        while let Some(event) = monitor.as_ref().and_then(|monitor| monitor.try_next()) {
+            log::info!("{:?}", event);
+            match (event, recovery) {
+                (librazer::monitor::DeviceEvent::Added { pid, .. }, Some((_, delay)))
+                    if pid == device.info().pid => recovery = Some((now, delay)),
+                _ => (),
+            }
+        }
+
+        if let Some((retry_at, delay)) = recovery {
+            if now < retry_at {
+                return;
+            }
+            let result = match device.reconnect() {
+                Ok(()) => init(&mut tray_icon, &device),
+                // simulated devices are not reopened
+                Err(librazer::Error::InvalidState(_)) => device
+                    .init()
+                    .map_err(Into::into)
+                    .and_then(|_| init(&mut tray_icon, &device)),
+                Err(e) => Err(e.into()),
+            };
+            match result {
+                Ok(new_state) => {
+                    log::info!("recovered");
+                    state = new_state;
+                    recovery = None;
+                }
+                Err(e) => {
+                    let delay = (delay * 2).min(std::time::Duration::from_secs(60));
+                    log::error!("failed to recover: {:?}, next attempt in {:?}", e, delay);
+                    recovery = Some((now + delay, delay));
+                }
+            }
+            return;
+        }
+
         if let Err(e) = (|| -> Result<()> {
             if let Ok(event) = menu_channel.try_recv() {
                 log::info!("Menu Event {:?}", event.id);
@@ -1018,11 +995,11 @@ fn main() -> Result<()> {
 
             state.ac_power = get_power_state()?;
             if state.ac_power && state.device_state != state.ac_state {
-                let new_device_state = state.ac_state.clone();
+                let new_device_state = state.ac_state;
                 log::info!("new_device_state 3 {:?}", new_device_state);
                 state.update(&mut tray_icon, new_device_state, &device)?;
-            } else if state.ac_power == false && state.device_state != state.battery_state {
-                let new_device_state = state.battery_state.clone();
+            } else if !state.ac_power && state.device_state != state.battery_state {
+                let new_device_state = state.battery_state;
                 log::info!("new_device_state 3 {:?}", new_device_state);
                 state.update(&mut tray_icon, new_device_state, &device)?;
             } 
@@ -1035,7 +1012,9 @@ fn main() -> Result<()> {
                 if active_device_state != state.device_state {
                     log::warn!("overriding externally modified state {:?},",
                               active_device_state);
-                    state.update(&mut tray_icon, state.device_state, &device)?;
+                    let new_device_state = state.device_state;
+                    state.device_state = active_device_state;
+                    state.update(&mut tray_icon, new_device_state, &device)?;
                } else {
                     tray_icon.set_tooltip(Some(state.tooltip()?))?;
                }
@@ -1043,19 +1022,26 @@ fn main() -> Result<()> {
 
             Ok(())
         })() {
-            loop {
-                log::error!("trying to recover from: {:?}", e);
-                match init(&mut tray_icon, &device) {
-                    Ok(new_state) => {
-                        state = new_state;
-                        break;
-                    },
-                    Err(e) => {
-                        log::error!("failed to recover: {:?}", e);
-                        *control_flow = ControlFlow::WaitUntil(now + std::time::Duration::from_millis(1000));
-                    }
+            // the state is intact, nothing to recover from
+            match e.downcast_ref() {
+                Some(librazer::Error::NotSupported { command }) => {
+                    log::warn!("command {:#06x} is not supported by this device", command);
+                    return;
+                }
+                // e.g. razer-cli is running, try again on the next tick
+                Some(e @ librazer::Error::Locked { .. }) => {
+                    log::warn!("{}", e);
+                    return;
+                }
+                // e.g. a boost the model does not take, the device is back in the previous state
+                Some(librazer::Error::Transaction(report)) if report.rolled_back() => {
+                    log::warn!("{}", report);
+                    return;
                 }
+                _ => (),
             }
+            log::error!("trying to recover from: {:?}", e);
+            recovery = Some((now, std::time::Duration::from_millis(500)));
         }
     })
 }